@@ -0,0 +1,614 @@
+//! A blocking NFS client.
+//!
+//! The types in this module mirror the asynchronous [`Client`](crate::Client) and
+//! [`File`](crate::File), but call the synchronous libnfs functions directly on the calling
+//! thread and don't need a tokio runtime. [`File`] implements [`std::io::Read`],
+//! [`std::io::Write`] and [`std::io::Seek`], so it can be used with anything built on top of the
+//! standard I/O traits.
+//!
+//! A libnfs service thread handles the socket, so a [`Client`] and the files opened through it
+//! can be used from multiple threads at once.
+
+use crate::{
+    client::{self, Context},
+    AsCString,
+};
+use libnfs_sys as libnfs;
+use nix::{fcntl::OFlag, sys::stat::Mode, unistd::AccessFlags, unistd::Whence};
+use std::{
+    ffi::c_void,
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem,
+    path::{Path, PathBuf},
+    ptr,
+    sync::{atomic::Ordering, Arc},
+    time::SystemTime,
+};
+
+/// A blocking NFS client.
+///
+/// Created with [`Client::mount`], or with
+/// [`ClientBuilder::mount_blocking`](crate::ClientBuilder::mount_blocking) to configure the mount.
+pub struct Client {
+    context: Arc<Context>,
+}
+
+impl Client {
+    pub(crate) fn new(context: Arc<Context>) -> Client {
+        Client { context }
+    }
+
+    pub fn mount<T: crate::IntoUrl>(url: T) -> crate::Result<Client> {
+        crate::ClientBuilder::new().mount_blocking(url)
+    }
+
+    /// Unmounts the export.
+    ///
+    /// Fails with `EBUSY` if any file opened through the client hasn't been dropped yet.
+    pub fn umount(self) -> crate::Result<()> {
+        self.context.annotate_sync("umount", None, || unsafe {
+            self.context.check_no_open_files()?;
+            libnfs::nfs_mt_service_thread_stop(self.context.nfs);
+            let res = self
+                .context
+                .check_retcode(libnfs::nfs_umount(self.context.nfs));
+            self.context.set_unmounted();
+            res
+        })
+    }
+
+    pub fn access<P: AsRef<Path>>(&self, path: P) -> crate::Result<AccessFlags> {
+        self.context
+            .annotate_sync("access", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+
+                unsafe {
+                    self.context
+                        .check_retcode_ret(libnfs::nfs_access2(self.context.nfs, path.as_ptr()))
+                        .map(AccessFlags::from_bits_truncate)
+                }
+            })
+    }
+
+    pub fn chmod<P: AsRef<Path>>(&self, path: P, mode: Mode) -> crate::Result<()> {
+        self.context
+            .annotate_sync("chmod", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+
+                unsafe {
+                    self.context.check_retcode(libnfs::nfs_chmod(
+                        self.context.nfs,
+                        path.as_ptr(),
+                        mode.bits() as i32,
+                    ))
+                }
+            })
+    }
+
+    pub fn chown<P: AsRef<Path>>(&self, path: P, uid: u32, gid: u32) -> crate::Result<()> {
+        self.context
+            .annotate_sync("chown", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+
+                unsafe {
+                    self.context.check_retcode(libnfs::nfs_chown(
+                        self.context.nfs,
+                        path.as_ptr(),
+                        uid as i32,
+                        gid as i32,
+                    ))
+                }
+            })
+    }
+
+    /// Opens a file in write-only mode, creating it if it doesn't exist and truncating it if it
+    /// does.
+    pub fn create<P: AsRef<Path>>(&self, path: P) -> crate::Result<File> {
+        crate::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open_blocking(self, path)
+    }
+
+    /// Creates a directory and all of its missing parents, see
+    /// [`Client::create_dir_all`](crate::Client::create_dir_all).
+    pub fn create_dir_all<P: AsRef<Path>>(&self, path: P, mode: Mode) -> crate::Result<()> {
+        let path = path.as_ref();
+        let mut missing: Vec<&Path> = Vec::new();
+
+        for dir in path.ancestors().filter(|dir| !dir.as_os_str().is_empty()) {
+            if client::is_existing_dir(&self.context, dir, self.stat(dir))? {
+                break;
+            }
+            missing.push(dir);
+        }
+
+        for dir in missing.into_iter().rev() {
+            if let Err(e) = self.mkdir(dir, mode) {
+                if !(client::is_already_exists(&e) && self.stat(dir)?.is_dir()) {
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn link<P: AsRef<Path>, Q: AsRef<Path>>(&self, original: P, link: Q) -> crate::Result<()> {
+        self.context.annotate_sync("link", Some(link.as_ref()), || {
+            let original = original.as_cstring()?;
+            let link = link.as_cstring()?;
+
+            unsafe {
+                self.context.check_retcode(libnfs::nfs_link(
+                    self.context.nfs,
+                    original.as_ptr(),
+                    link.as_ptr(),
+                ))
+            }
+        })
+    }
+
+    pub fn lstat<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::Metadata> {
+        self.context
+            .annotate_sync("lstat", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+
+                unsafe {
+                    let mut stat = mem::MaybeUninit::uninit();
+
+                    self.context.check_retcode(libnfs::nfs_lstat64(
+                        self.context.nfs,
+                        path.as_ptr(),
+                        stat.as_mut_ptr(),
+                    ))?;
+
+                    Ok(stat.assume_init().into())
+                }
+            })
+    }
+
+    pub fn mkdir<P: AsRef<Path>>(&self, path: P, mode: Mode) -> crate::Result<()> {
+        self.context
+            .annotate_sync("mkdir", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+
+                unsafe {
+                    self.context.check_retcode(libnfs::nfs_mkdir2(
+                        self.context.nfs,
+                        path.as_ptr(),
+                        mode.bits() as i32,
+                    ))
+                }
+            })
+    }
+
+    /// Opens a file in read-only mode.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> crate::Result<File> {
+        crate::OpenOptions::new()
+            .read(true)
+            .open_blocking(self, path)
+    }
+
+    pub(crate) fn open_with_flags<P: AsRef<Path>>(
+        &self,
+        path: P,
+        flags: OFlag,
+        mode: Mode,
+    ) -> crate::Result<File> {
+        self.context.annotate_sync("open", Some(path.as_ref()), || {
+            let cpath = path.as_cstring()?;
+
+            unsafe {
+                let mut file = mem::MaybeUninit::uninit();
+
+                self.context.check_retcode(libnfs::nfs_open2(
+                    self.context.nfs,
+                    cpath.as_ptr(),
+                    flags.bits(),
+                    mode.bits() as i32,
+                    file.as_mut_ptr(),
+                ))?;
+
+                self.context.open_files.fetch_add(1, Ordering::Release);
+                Ok(File {
+                    context: Arc::clone(&self.context),
+                    file: file.assume_init(),
+                    path: path.as_ref().to_path_buf(),
+                })
+            }
+        })
+    }
+
+    /// Reads the entire contents of a file into a bytes vector.
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> crate::Result<Vec<u8>> {
+        let mut file = self.open(path)?;
+        let size = file.stat()?.len();
+        let mut contents = Vec::with_capacity(size as usize);
+
+        file.read_to_end(&mut contents)
+            .map_err(crate::error::from_io)?;
+
+        Ok(contents)
+    }
+
+    /// Returns an iterator over the entries of a directory.
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> crate::Result<ReadDir> {
+        self.context
+            .annotate_sync("read_dir", Some(path.as_ref()), || {
+                let path = path.as_ref().to_path_buf();
+                let cpath = path.as_cstring()?;
+
+                unsafe {
+                    let mut dir = mem::MaybeUninit::uninit();
+
+                    self.context.check_retcode(libnfs::nfs_opendir(
+                        self.context.nfs,
+                        cpath.as_ptr(),
+                        dir.as_mut_ptr(),
+                    ))?;
+
+                    Ok(ReadDir(crate::ReadDir::new(
+                        Arc::clone(&self.context),
+                        crate::dir::Dir(dir.assume_init()),
+                        path,
+                    )))
+                }
+            })
+    }
+
+    /// Reads the entire contents of a file into a string.
+    pub fn read_to_string<P: AsRef<Path>>(&self, path: P) -> crate::Result<String> {
+        client::into_string(self.read(path)?)
+    }
+
+    pub fn readlink<P: AsRef<Path>>(&self, path: P) -> crate::Result<PathBuf> {
+        self.context
+            .annotate_sync("readlink", Some(path.as_ref()), || {
+                self.context.readlink_blocking(&path.as_cstring()?)
+            })
+    }
+
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> crate::Result<()> {
+        self.context
+            .annotate_sync("rename", Some(from.as_ref()), || {
+                let from = from.as_cstring()?;
+                let to = to.as_cstring()?;
+
+                unsafe {
+                    self.context.check_retcode(libnfs::nfs_rename(
+                        self.context.nfs,
+                        from.as_ptr(),
+                        to.as_ptr(),
+                    ))
+                }
+            })
+    }
+
+    pub fn rmdir<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
+        self.context
+            .annotate_sync("rmdir", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+
+                unsafe {
+                    self.context
+                        .check_retcode(libnfs::nfs_rmdir(self.context.nfs, path.as_ptr()))
+                }
+            })
+    }
+
+    pub fn set_times<P: AsRef<Path>>(
+        &self,
+        path: P,
+        accessed: SystemTime,
+        modified: SystemTime,
+    ) -> crate::Result<()> {
+        self.context
+            .annotate_sync("set_times", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+                let mut times = [
+                    crate::client::timeval(accessed)?,
+                    crate::client::timeval(modified)?,
+                ];
+
+                unsafe {
+                    self.context.check_retcode(libnfs::nfs_utimes(
+                        self.context.nfs,
+                        path.as_ptr(),
+                        times.as_mut_ptr(),
+                    ))
+                }
+            })
+    }
+
+    pub fn stat<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::Metadata> {
+        self.context.annotate_sync("stat", Some(path.as_ref()), || {
+            let path = path.as_cstring()?;
+
+            unsafe {
+                let mut stat = mem::MaybeUninit::uninit();
+
+                self.context.check_retcode(libnfs::nfs_stat64(
+                    self.context.nfs,
+                    path.as_ptr(),
+                    stat.as_mut_ptr(),
+                ))?;
+
+                Ok(stat.assume_init().into())
+            }
+        })
+    }
+
+    pub fn statvfs<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::FsStats> {
+        self.context
+            .annotate_sync("statvfs", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+
+                unsafe {
+                    let mut stats = mem::MaybeUninit::uninit();
+
+                    self.context.check_retcode(libnfs::nfs_statvfs64(
+                        self.context.nfs,
+                        path.as_ptr(),
+                        stats.as_mut_ptr(),
+                    ))?;
+
+                    Ok(stats.assume_init().into())
+                }
+            })
+    }
+
+    pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        original: P,
+        link: Q,
+    ) -> crate::Result<()> {
+        self.context
+            .annotate_sync("symlink", Some(link.as_ref()), || {
+                let original = original.as_cstring()?;
+                let link = link.as_cstring()?;
+
+                unsafe {
+                    self.context.check_retcode(libnfs::nfs_symlink(
+                        self.context.nfs,
+                        original.as_ptr(),
+                        link.as_ptr(),
+                    ))
+                }
+            })
+    }
+
+    pub fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> crate::Result<()> {
+        self.context
+            .annotate_sync("truncate", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+
+                unsafe {
+                    self.context.check_retcode(libnfs::nfs_truncate(
+                        self.context.nfs,
+                        path.as_ptr(),
+                        len,
+                    ))
+                }
+            })
+    }
+
+    /// Returns `Ok(true)` if the path points at an existing entity, see
+    /// [`Client::try_exists`](crate::Client::try_exists).
+    pub fn try_exists<P: AsRef<Path>>(&self, path: P) -> crate::Result<bool> {
+        client::exists(self.stat(path))
+    }
+
+    pub fn unlink<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
+        self.context
+            .annotate_sync("unlink", Some(path.as_ref()), || {
+                let path = path.as_cstring()?;
+
+                unsafe {
+                    self.context
+                        .check_retcode(libnfs::nfs_unlink(self.context.nfs, path.as_ptr()))
+                }
+            })
+    }
+
+    /// Writes `contents` to a file, creating it if it doesn't exist and replacing its contents
+    /// if it does.
+    pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&self, path: P, contents: C) -> crate::Result<()> {
+        let mut file = self.create(path)?;
+
+        file.write_all(contents.as_ref())
+            .map_err(crate::error::from_io)?;
+        file.close()
+    }
+}
+
+/// An iterator over the entries of a directory.
+///
+/// Returned by [`Client::read_dir`]. The `.` and `..` entries are skipped.
+pub struct ReadDir(crate::ReadDir);
+
+impl Iterator for ReadDir {
+    type Item = crate::DirEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_entry()
+    }
+}
+
+/// A file opened by a blocking [`Client`].
+///
+/// I/O is unbuffered: every `read` and `write` call is a round-trip to the server. Wrap the file
+/// in a [`std::io::BufReader`] or [`std::io::BufWriter`] for small reads and writes.
+pub struct File {
+    context: Arc<Context>,
+    file: *mut libnfs::nfsfh,
+    path: PathBuf,
+}
+
+unsafe impl Send for File {}
+unsafe impl Sync for File {}
+
+impl File {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Converts the error of an operation on the file to an `io::Error`, keeping its context.
+    fn io_error(&self, operation: &'static str, e: crate::Error) -> io::Error {
+        e.with_context(operation, Some(&self.path), &self.context.url)
+            .into()
+    }
+
+    pub fn stat(&self) -> crate::Result<crate::Metadata> {
+        self.context
+            .annotate_sync("stat", Some(self.path()), || unsafe {
+                let mut stat = mem::MaybeUninit::uninit();
+
+                self.context.check_retcode(libnfs::nfs_fstat64(
+                    self.context.nfs,
+                    self.file,
+                    stat.as_mut_ptr(),
+                ))?;
+
+                Ok(stat.assume_init().into())
+            })
+    }
+
+    /// Reads up to `buf.len()` bytes at `offset` without moving the file cursor.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> crate::Result<usize> {
+        self.context
+            .annotate_sync("read_at", Some(self.path()), || unsafe {
+                self.context
+                    .check_retcode_ret(libnfs::nfs_pread(
+                        self.context.nfs,
+                        self.file,
+                        offset,
+                        buf.len() as u64,
+                        buf.as_mut_ptr() as *mut c_void,
+                    ))
+                    .map(|n| n as usize)
+            })
+            .map_err(|e| e.with_offset(offset))
+    }
+
+    /// Writes up to `buf.len()` bytes at `offset` without moving the file cursor.
+    pub fn write_at(&self, offset: u64, buf: &[u8]) -> crate::Result<usize> {
+        self.context
+            .annotate_sync("write_at", Some(self.path()), || unsafe {
+                self.context
+                    .check_retcode_ret(libnfs::nfs_pwrite(
+                        self.context.nfs,
+                        self.file,
+                        offset,
+                        buf.len() as u64,
+                        buf.as_ptr() as *const c_void,
+                    ))
+                    .map(|n| n as usize)
+            })
+            .map_err(|e| e.with_offset(offset))
+    }
+
+    pub fn set_len(&self, size: u64) -> crate::Result<()> {
+        self.context
+            .annotate_sync("set_len", Some(self.path()), || unsafe {
+                self.context
+                    .check_retcode(libnfs::nfs_ftruncate(self.context.nfs, self.file, size))
+            })
+    }
+
+    pub fn sync_all(&self) -> crate::Result<()> {
+        self.context
+            .annotate_sync("sync_all", Some(self.path()), || unsafe {
+                self.context
+                    .check_retcode(libnfs::nfs_fsync(self.context.nfs, self.file))
+            })
+    }
+
+    /// Commits pending writes and closes the file.
+    ///
+    /// Dropping a file ignores any errors, while servers may report failed writes only when they
+    /// are committed. Use `close` to find out about them.
+    pub fn close(mut self) -> crate::Result<()> {
+        let file = mem::replace(&mut self.file, ptr::null_mut());
+
+        self.context
+            .annotate_sync("close", Some(self.path()), || unsafe {
+                self.context
+                    .check_retcode(libnfs::nfs_close(self.context.nfs, file))
+            })
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        unsafe {
+            let offset = libnfs::nfs_get_current_offset(self.file);
+            self.context
+                .check_retcode_ret(libnfs::nfs_read(
+                    self.context.nfs,
+                    self.file,
+                    buf.len() as u64,
+                    buf.as_mut_ptr() as *mut c_void,
+                ))
+                .map(|n| n as usize)
+                .map_err(|e| self.io_error("read", e.with_offset(offset)))
+        }
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        unsafe {
+            let offset = libnfs::nfs_get_current_offset(self.file);
+            self.context
+                .check_retcode_ret(libnfs::nfs_write(
+                    self.context.nfs,
+                    self.file,
+                    buf.len() as u64,
+                    buf.as_ptr() as *const c_void,
+                ))
+                .map(|n| n as usize)
+                .map_err(|e| self.io_error("write", e.with_offset(offset)))
+        }
+    }
+
+    // Writes go straight to the server, there is nothing to flush.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => (offset as i64, Whence::SeekSet),
+            SeekFrom::Current(offset) => (offset, Whence::SeekCur),
+            SeekFrom::End(offset) => (offset, Whence::SeekEnd),
+        };
+        let mut cur_offset: u64 = 0;
+
+        unsafe {
+            let start = libnfs::nfs_get_current_offset(self.file);
+            self.context
+                .check_retcode(libnfs::nfs_lseek(
+                    self.context.nfs,
+                    self.file,
+                    offset,
+                    whence as i32,
+                    &mut cur_offset as *mut u64,
+                ))
+                .map(|_| cur_offset)
+                .map_err(|e| self.io_error("seek", e.with_offset(start)))
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        if !self.context.nfs.is_null() && !self.file.is_null() {
+            unsafe { libnfs::nfs_close(self.context.nfs, self.file) };
+        }
+        self.context.open_files.fetch_sub(1, Ordering::Release);
+    }
+}
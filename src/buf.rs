@@ -2,8 +2,6 @@ use std::cmp;
 
 use tokio::io::ReadBuf;
 
-pub(crate) const MAX_BUF: usize = 16 * 1024;
-
 pub(crate) struct Buf {
     buf: Vec<u8>,
     pos: usize,
@@ -37,25 +35,36 @@ impl Buf {
     pub(crate) fn copy_to(&mut self, dst: &mut ReadBuf<'_>) -> usize {
         let n = cmp::min(self.len(), dst.remaining());
         dst.put_slice(&self.bytes()[..n]);
-        self.pos += n;
+        self.consume(n);
+
+        n
+    }
+
+    pub(crate) fn consume(&mut self, n: usize) {
+        self.pos = cmp::min(self.pos + n, self.buf.len());
 
         if self.pos == self.buf.len() {
             self.buf.truncate(0);
             self.pos = 0;
         }
-
-        n
     }
 
-    pub(crate) fn copy_from(&mut self, src: &[u8]) -> usize {
+    pub(crate) fn copy_from(&mut self, src: &[u8], max_buf_size: usize) -> usize {
         assert!(self.is_empty());
 
-        let n = cmp::min(src.len(), MAX_BUF);
+        let n = cmp::min(src.len(), max_buf_size);
 
         self.buf.extend_from_slice(&src[..n]);
         n
     }
 
+    pub(crate) fn replace(&mut self, data: Vec<u8>) {
+        assert!(self.is_empty());
+
+        self.buf = data;
+        self.pos = 0;
+    }
+
     pub(crate) fn bytes(&self) -> &[u8] {
         &self.buf[self.pos..]
     }
@@ -64,11 +73,9 @@ impl Buf {
         &mut self.buf[self.pos..]
     }
 
-    pub(crate) fn ensure_capacity_for(&mut self, bytes: &ReadBuf<'_>) {
+    pub(crate) fn ensure_capacity(&mut self, len: usize) {
         assert!(self.is_empty());
 
-        let len = cmp::min(bytes.remaining(), MAX_BUF);
-
         if self.buf.len() < len {
             self.buf.reserve(len - self.buf.len());
         }
@@ -1,14 +1,63 @@
-use crate::{AsCString, ToStringLossy};
+use crate::{driver::Driver, AsCString, MountOptions, ToStringLossy, Version};
+use futures_core::Stream;
 use libnfs_sys as libnfs;
-use nix::{fcntl::OFlag, sys::stat::Mode, unistd::AccessFlags};
-use std::{ffi::CString, io, mem, path::Path, sync::Arc};
-use tokio::task;
+use nix::{errno::Errno, fcntl::OFlag, sys::stat::Mode, unistd::AccessFlags};
+use std::{
+    ffi::{c_char, CStr, CString, OsStr},
+    future::{poll_fn, Future},
+    io, mem,
+    ops::Deref,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::Notify, task};
 
-pub(crate) struct Context(pub(crate) *mut libnfs::nfs_context);
+// Maximum length of a symlink target, including the terminating NUL.
+const PATH_MAX: usize = 4096;
+
+// Number of concurrent unlinks issued by `remove_dir_all`.
+const REMOVE_CONCURRENCY: usize = 16;
+
+// Mount states of a context.
+const MOUNTED: u8 = 0;
+const UNMOUNTING: u8 = 1;
+const UNMOUNTED: u8 = 2;
+
+pub(crate) struct Context {
+    pub(crate) nfs: *mut libnfs::nfs_context,
+    pub(crate) driver: Option<Driver>,
+    pub(crate) max_buf_size: Option<usize>,
+    pub(crate) url: String,
+    // Number of file handles opened through the context and not closed yet.
+    pub(crate) open_files: AtomicUsize,
+    state: AtomicU8,
+    // Number of operations running on the context, and the notification `umount` waits on for
+    // it to drop to zero.
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+// Registers an operation as in flight for as long as it's alive.
+pub(crate) struct InFlight<C: Deref<Target = Context>>(C);
+
+impl<C: Deref<Target = Context>> Drop for InFlight<C> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
 
 impl Context {
     fn get_last_error(&self) -> String {
-        unsafe { libnfs::nfs_get_error(self.0) }.to_string_lossy()
+        unsafe { libnfs::nfs_get_error(self.nfs) }.to_string_lossy()
     }
 
     pub(crate) fn check_retcode_ret(&self, code: i32) -> crate::Result<i32> {
@@ -27,6 +76,139 @@ impl Context {
 
         Ok(())
     }
+
+    // Runs an operation, attaching its name, path and the server URL to the error it fails with.
+    pub(crate) async fn annotate<T, F>(
+        &self,
+        operation: &'static str,
+        path: Option<&Path>,
+        f: F,
+    ) -> crate::Result<T>
+    where
+        F: Future<Output = crate::Result<T>>,
+    {
+        let res = match self.enter() {
+            Ok(_in_flight) => f.await,
+            Err(e) => Err(e),
+        };
+        res.map_err(|e| e.with_context(operation, path, &self.url))
+    }
+
+    // Same as `annotate`, for the blocking client.
+    pub(crate) fn annotate_sync<T, F>(
+        &self,
+        operation: &'static str,
+        path: Option<&Path>,
+        f: F,
+    ) -> crate::Result<T>
+    where
+        F: FnOnce() -> crate::Result<T>,
+    {
+        let res = match self.enter() {
+            Ok(_in_flight) => f(),
+            Err(e) => Err(e),
+        };
+        res.map_err(|e| e.with_context(operation, path, &self.url))
+    }
+
+    // Registers an operation, failing if the export has been unmounted or is being unmounted.
+    fn enter(&self) -> crate::Result<InFlight<&Context>> {
+        // Paired with the store in `begin_umount`: either the operation sees the new state, or
+        // `umount` sees the operation and waits for it.
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight(self);
+        if self.state.load(Ordering::SeqCst) != MOUNTED {
+            return Err(not_mounted());
+        }
+
+        Ok(in_flight)
+    }
+
+    // Registers an operation a file runs in the background. It can't be refused like the ones
+    // registered with `enter`, but then the file keeps `umount` from going through anyway.
+    pub(crate) fn hold(self: &Arc<Self>) -> InFlight<Arc<Context>> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(Arc::clone(self))
+    }
+
+    // Stops new operations from starting. Only one caller gets to unmount the export.
+    fn begin_umount(&self) -> crate::Result<()> {
+        self.state
+            .compare_exchange(MOUNTED, UNMOUNTING, Ordering::SeqCst, Ordering::SeqCst)
+            .map(|_| ())
+            .map_err(|_| not_mounted())
+    }
+
+    // Waits for the operations started before `begin_umount` to finish.
+    async fn drain(&self) {
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            if self.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    // Marks the export unmounted, whether or not the server acknowledged the UMNT: the service
+    // thread is stopped either way.
+    pub(crate) fn set_unmounted(&self) {
+        self.state.store(UNMOUNTED, Ordering::SeqCst);
+    }
+
+    // Unmounting would pull the export from under the files still open, so refuse to.
+    pub(crate) fn check_no_open_files(&self) -> crate::Result<()> {
+        match self.open_files.load(Ordering::Acquire) {
+            0 => Ok(()),
+            n => Err(crate::error::nfs(
+                format!("{n} file(s) opened through the client are still open"),
+                Errno::EBUSY,
+            )),
+        }
+    }
+
+    // Reads the target of a symlink with the synchronous libnfs call.
+    pub(crate) fn readlink_blocking(&self, path: &CStr) -> crate::Result<PathBuf> {
+        let mut buf = vec![0u8; PATH_MAX];
+
+        self.check_retcode(unsafe {
+            libnfs::nfs_readlink(
+                self.nfs,
+                path.as_ptr(),
+                buf.as_mut_ptr() as *mut c_char,
+                buf.len() as i32,
+            )
+        })?;
+
+        let target = CStr::from_bytes_until_nul(&buf).map_err(|_| {
+            crate::error::nfs("link target is too long", io::ErrorKind::InvalidData)
+        })?;
+        Ok(PathBuf::from(OsStr::from_bytes(target.to_bytes())))
+    }
+
+    // Largest READ transfer negotiated with the server.
+    pub(crate) fn read_max(&self) -> usize {
+        (unsafe { libnfs::nfs_get_readmax(self.nfs) } as usize).max(1)
+    }
+
+    // Largest WRITE transfer negotiated with the server.
+    pub(crate) fn write_max(&self) -> usize {
+        (unsafe { libnfs::nfs_get_writemax(self.nfs) } as usize).max(1)
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        if !self.nfs.is_null() {
+            match self.driver {
+                // The driver owns the context, let it destroy it in between servicing requests
+                Some(ref driver) => driver.shutdown(),
+                None => unsafe { libnfs::nfs_destroy_context(self.nfs) },
+            }
+        }
+    }
 }
 
 unsafe impl Send for Context {}
@@ -44,24 +226,161 @@ impl Drop for Url {
 
 unsafe impl Send for Url {}
 
+/// An asynchronous NFS client.
+///
+/// Cloning a client is cheap: the clones share the mount, which stays up until one of them calls
+/// [`umount`](Client::umount).
+#[derive(Clone)]
 pub struct Client {
     context: Arc<Context>,
 }
 
-impl Client {
-    pub async fn mount<T: crate::IntoUrl>(url: T) -> crate::Result<Client> {
-        let context = unsafe {
-            let context = Arc::new(Context(libnfs::nfs_init_context()));
-            if context.0.is_null() {
+/// The way libnfs is driven by a [`Client`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Synchronous libnfs calls on the blocking thread pool, with a libnfs service thread
+    /// handling the socket.
+    ///
+    /// Concurrency is limited by the size of the blocking thread pool.
+    #[default]
+    Threaded,
+    /// Asynchronous libnfs calls driven by the tokio reactor.
+    ///
+    /// No thread is occupied while a request is in flight, so thousands of operations can be
+    /// outstanding at once. Requires a tokio runtime with I/O and time drivers enabled.
+    Async,
+}
+
+/// A builder to configure and mount a [`Client`].
+///
+/// Options set on the builder take precedence over the ones passed in the URL query string.
+#[derive(Debug, Clone, Default)]
+pub struct ClientBuilder {
+    options: MountOptions,
+    timeout: Option<Duration>,
+    backend: Backend,
+    max_buf_size: Option<usize>,
+}
+
+impl ClientBuilder {
+    pub fn new() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// Sets the user ID used for AUTH_UNIX credentials.
+    pub fn uid(mut self, uid: u32) -> ClientBuilder {
+        self.options.uid = Some(uid);
+        self
+    }
+
+    /// Sets the group ID used for AUTH_UNIX credentials.
+    pub fn gid(mut self, gid: u32) -> ClientBuilder {
+        self.options.gid = Some(gid);
+        self
+    }
+
+    /// Sets the NFS protocol version.
+    pub fn version(mut self, version: Version) -> ClientBuilder {
+        self.options.version = Some(version);
+        self
+    }
+
+    /// Sets the timeout for synchronous operations.
+    pub fn timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the size of the read-ahead window in bytes. `0` disables read-ahead.
+    pub fn readahead(mut self, size: u32) -> ClientBuilder {
+        self.options.readahead = Some(size);
+        self
+    }
+
+    /// Sets the number of pages in the per-file page cache. `0` disables the page cache.
+    pub fn pagecache(mut self, pages: u32) -> ClientBuilder {
+        self.options.pagecache = Some(pages);
+        self
+    }
+
+    /// Sets the number of reconnect attempts after the connection to the server is lost.
+    ///
+    /// `None` retries forever, `Some(0)` disables reconnecting.
+    pub fn auto_reconnect(mut self, retries: Option<u32>) -> ClientBuilder {
+        self.options.auto_reconnect =
+            Some(retries.map_or(-1, |r| r.try_into().unwrap_or(i32::MAX)));
+        self
+    }
+
+    /// Sets the number of SYN retransmits when connecting to the server.
+    pub fn tcp_syncnt(mut self, count: u32) -> ClientBuilder {
+        self.options.tcp_syncnt = Some(count);
+        self
+    }
+
+    /// Enables or disables the directory cache.
+    pub fn dircache(mut self, enabled: bool) -> ClientBuilder {
+        self.options.dircache = Some(enabled);
+        self
+    }
+
+    /// Selects how libnfs is driven. Defaults to [`Backend::Threaded`].
+    pub fn backend(mut self, backend: Backend) -> ClientBuilder {
+        self.backend = backend;
+        self
+    }
+
+    /// Sets the size of the buffer used by [`File`](crate::File) for reads and writes.
+    ///
+    /// Defaults to the maximum READ and WRITE transfer sizes negotiated with the server, so that
+    /// every RPC moves as much data as possible.
+    pub fn max_buf_size(mut self, size: usize) -> ClientBuilder {
+        self.max_buf_size = Some(size.max(1));
+        self
+    }
+
+    /// Sets all mount options at once, replacing the ones set so far.
+    pub fn options(mut self, options: MountOptions) -> ClientBuilder {
+        self.options = options;
+        self
+    }
+
+    unsafe fn apply(&self, context: &Context) -> crate::Result<()> {
+        self.options.apply(context)?;
+
+        if let Some(timeout) = self.timeout {
+            let timeout = timeout.as_millis().try_into().unwrap_or(i32::MAX);
+            libnfs::nfs_set_timeout(context.nfs, timeout);
+        }
+
+        Ok(())
+    }
+
+    // Creates a context configured by the builder and parses the export URL into it.
+    fn init<T: crate::IntoUrl>(&self, url: T) -> crate::Result<(Context, Url)> {
+        let url = url.into_url()?;
+
+        unsafe {
+            let context = Context {
+                nfs: libnfs::nfs_init_context(),
+                driver: None,
+                max_buf_size: self.max_buf_size,
+                url: url.to_string(),
+                open_files: AtomicUsize::new(0),
+                state: AtomicU8::new(MOUNTED),
+                in_flight: AtomicUsize::new(0),
+                idle: Notify::new(),
+            };
+            if context.nfs.is_null() {
                 return Err(crate::error::nfs(
                     "can't initialize libnfs context",
                     io::ErrorKind::OutOfMemory,
                 ));
             }
 
-            let url = CString::new(url.into_url()?.as_str())
-                .map_err(|e| crate::error::nfs("can't parse URL", e))?;
-            let url = Url(libnfs::nfs_parse_url_dir(context.0, url.as_ptr()));
+            let url =
+                CString::new(url.as_str()).map_err(|e| crate::error::nfs("can't parse URL", e))?;
+            let url = Url(libnfs::nfs_parse_url_dir(context.nfs, url.as_ptr()));
             if url.0.is_null() {
                 return Err(crate::error::nfs(
                     context.get_last_error(),
@@ -69,131 +388,1013 @@ impl Client {
                 ));
             }
 
-            {
-                let context = Arc::clone(&context);
-                task::spawn_blocking(move || {
-                    let url = url;
+            self.apply(&context)?;
 
-                    context.check_retcode(libnfs::nfs_mount(
-                        context.0,
-                        (*url.0).server,
-                        (*url.0).path,
-                    ))?;
-                    context.check_retcode(libnfs::nfs_mt_service_thread_start(context.0))
-                })
-                .await??;
-            }
+            Ok((context, url))
+        }
+    }
+
+    pub async fn mount<T: crate::IntoUrl>(self, url: T) -> crate::Result<Client> {
+        let (mut context, url) = self.init(url)?;
+
+        let context = unsafe {
+            match self.backend {
+                Backend::Threaded => {
+                    let context = Arc::new(context);
+
+                    {
+                        let context = Arc::clone(&context);
+                        task::spawn_blocking(move || mount_threaded(&context, url)).await??;
+                    }
+
+                    context
+                }
+                Backend::Async => {
+                    context.driver = Some(Driver::start(context.nfs));
+                    let context = Arc::new(context);
+
+                    let driver = context.driver.as_ref().unwrap();
+                    let mount = driver.call(
+                        move |nfs, cb, data| {
+                            let url = url;
+
+                            libnfs::nfs_mount_async(nfs, (*url.0).server, (*url.0).path, cb, data)
+                        },
+                        |_, _| Ok(()),
+                    );
+                    context.annotate("mount", None, mount).await?;
 
-            context
+                    context
+                }
+            }
         };
 
         Ok(Client { context })
     }
 
-    pub async fn umount(self) -> crate::Result<()> {
+    /// Mounts a [`blocking::Client`](crate::blocking::Client) with the builder's options.
+    ///
+    /// The [`backend`](ClientBuilder::backend) option is ignored: the blocking client always
+    /// calls synchronous libnfs functions on the calling thread.
+    pub fn mount_blocking<T: crate::IntoUrl>(
+        self,
+        url: T,
+    ) -> crate::Result<crate::blocking::Client> {
+        let (context, url) = self.init(url)?;
+        let context = Arc::new(context);
+
+        unsafe { mount_threaded(&context, url)? };
+
+        Ok(crate::blocking::Client::new(context))
+    }
+}
+
+// Mounts the export and starts the libnfs service thread, which makes synchronous calls safe to
+// issue from multiple threads at once.
+unsafe fn mount_threaded(context: &Context, url: Url) -> crate::Result<()> {
+    context
+        .check_retcode(libnfs::nfs_mount(
+            context.nfs,
+            (*url.0).server,
+            (*url.0).path,
+        ))
+        .and_then(|_| context.check_retcode(libnfs::nfs_mt_service_thread_start(context.nfs)))
+        .map_err(|e| e.with_context("mount", None, &context.url))
+}
+
+impl Client {
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    pub async fn mount<T: crate::IntoUrl>(url: T) -> crate::Result<Client> {
+        ClientBuilder::new().mount(url).await
+    }
+
+    /// Unmounts the export.
+    ///
+    /// New operations on the client and all of its clones fail with `ENOTCONN` as soon as
+    /// unmounting starts. The ones already running, including the writes behind and reads ahead
+    /// of files that were dropped, are waited for before the export is unmounted.
+    ///
+    /// Fails with `EBUSY`, leaving the export mounted, if any file opened through the client
+    /// hasn't been closed or dropped yet. The libnfs context itself is destroyed once every clone
+    /// of the client and all of its files are gone.
+    pub async fn umount(&self) -> crate::Result<()> {
         let context = Arc::clone(&self.context);
+        async {
+            context.begin_umount()?;
+            // Files dropped before are closed only once their operations complete, and the
+            // operations still running may open new ones
+            context.drain().await;
+            if let Err(e) = context.check_no_open_files() {
+                context.state.store(MOUNTED, Ordering::SeqCst);
+                return Err(e);
+            }
+            // Wait for the handles closed in the meantime
+            context.drain().await;
 
-        task::spawn_blocking(move || unsafe {
-            libnfs::nfs_mt_service_thread_stop(context.0);
-            context.check_retcode(libnfs::nfs_umount(context.0))
-        })
-        .await?
+            let res = if let Some(driver) = &context.driver {
+                driver
+                    .call(
+                        |nfs, cb, data| unsafe { libnfs::nfs_umount_async(nfs, cb, data) },
+                        |_, _| Ok(()),
+                    )
+                    .await
+            } else {
+                let context = Arc::clone(&context);
+                task::spawn_blocking(move || unsafe {
+                    libnfs::nfs_mt_service_thread_stop(context.nfs);
+                    context.check_retcode(libnfs::nfs_umount(context.nfs))
+                })
+                .await
+                .map_err(crate::Error::from)
+                .and_then(|res| res)
+            };
+            context.set_unmounted();
+            res
+        }
+        .await
+        .map_err(|e| e.with_context("umount", None, &context.url))
     }
 
     pub async fn access<P: AsRef<Path>>(&self, path: P) -> crate::Result<AccessFlags> {
-        let context = Arc::clone(&self.context);
-        let path = path.as_cstring()?;
+        self.context
+            .annotate("access", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
 
-        task::spawn_blocking(move || unsafe {
-            context
-                .check_retcode_ret(libnfs::nfs_access2(context.0, path.as_ptr()))
-                .map(AccessFlags::from_bits_truncate)
-        })
-        .await?
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_access2_async(nfs, path.as_ptr(), cb, data)
+                            },
+                            |mode, _| Ok(AccessFlags::from_bits_truncate(mode)),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context
+                        .check_retcode_ret(libnfs::nfs_access2(context.nfs, path.as_ptr()))
+                        .map(AccessFlags::from_bits_truncate)
+                })
+                .await?
+            })
+            .await
+    }
+
+    pub async fn chmod<P: AsRef<Path>>(&self, path: P, mode: Mode) -> crate::Result<()> {
+        self.context
+            .annotate("chmod", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_chmod_async(
+                                    nfs,
+                                    path.as_ptr(),
+                                    mode.bits() as i32,
+                                    cb,
+                                    data,
+                                )
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_chmod(
+                        context.nfs,
+                        path.as_ptr(),
+                        mode.bits() as i32,
+                    ))
+                })
+                .await?
+            })
+            .await
+    }
+
+    pub async fn chown<P: AsRef<Path>>(&self, path: P, uid: u32, gid: u32) -> crate::Result<()> {
+        self.context
+            .annotate("chown", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_chown_async(
+                                    nfs,
+                                    path.as_ptr(),
+                                    uid as i32,
+                                    gid as i32,
+                                    cb,
+                                    data,
+                                )
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_chown(
+                        context.nfs,
+                        path.as_ptr(),
+                        uid as i32,
+                        gid as i32,
+                    ))
+                })
+                .await?
+            })
+            .await
+    }
+
+    /// Opens a file in write-only mode, creating it if it doesn't exist and truncating it if it
+    /// does.
+    pub async fn create<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::File> {
+        crate::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self, path)
+            .await
+    }
+
+    /// Creates a directory and all of its missing parents.
+    ///
+    /// Components created concurrently by someone else are not an error as long as they turn
+    /// out to be directories.
+    pub async fn create_dir_all<P: AsRef<Path>>(&self, path: P, mode: Mode) -> crate::Result<()> {
+        let path = path.as_ref();
+        let mut missing: Vec<&Path> = Vec::new();
+
+        // Find the deepest existing ancestor first, so that an existing tree costs a single
+        // round-trip.
+        for dir in path.ancestors().filter(|dir| !dir.as_os_str().is_empty()) {
+            if is_existing_dir(&self.context, dir, self.stat(dir).await)? {
+                break;
+            }
+            missing.push(dir);
+        }
+
+        for dir in missing.into_iter().rev() {
+            if let Err(e) = self.mkdir(dir, mode).await {
+                if !(is_already_exists(&e) && self.stat(dir).await?.is_dir()) {
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn link<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        original: P,
+        link: Q,
+    ) -> crate::Result<()> {
+        self.context
+            .annotate("link", Some(link.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let original = original.as_cstring()?;
+                let link = link.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_link_async(
+                                    nfs,
+                                    original.as_ptr(),
+                                    link.as_ptr(),
+                                    cb,
+                                    data,
+                                )
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_link(
+                        context.nfs,
+                        original.as_ptr(),
+                        link.as_ptr(),
+                    ))
+                })
+                .await?
+            })
+            .await
+    }
+
+    pub async fn lstat<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::Metadata> {
+        self.context
+            .annotate("lstat", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_lstat64_async(nfs, path.as_ptr(), cb, data)
+                            },
+                            |_, stat| Ok(unsafe { *(stat as *const libnfs::nfs_stat_64) }.into()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    let mut stat = mem::MaybeUninit::uninit();
+
+                    context.check_retcode(libnfs::nfs_lstat64(
+                        context.nfs,
+                        path.as_ptr(),
+                        stat.as_mut_ptr(),
+                    ))?;
+
+                    Ok(stat.assume_init().into())
+                })
+                .await?
+            })
+            .await
     }
 
     pub async fn mkdir<P: AsRef<Path>>(&self, path: P, mode: Mode) -> crate::Result<()> {
-        let context = Arc::clone(&self.context);
-        let path = path.as_cstring()?;
+        self.context
+            .annotate("mkdir", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
 
-        task::spawn_blocking(move || unsafe {
-            context.check_retcode(libnfs::nfs_mkdir2(
-                context.0,
-                path.as_ptr(),
-                mode.bits() as i32,
-            ))
-        })
-        .await?
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_mkdir2_async(
+                                    nfs,
+                                    path.as_ptr(),
+                                    mode.bits() as i32,
+                                    cb,
+                                    data,
+                                )
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_mkdir2(
+                        context.nfs,
+                        path.as_ptr(),
+                        mode.bits() as i32,
+                    ))
+                })
+                .await?
+            })
+            .await
     }
 
-    pub async fn open<P: AsRef<Path>>(
+    /// Opens a file in read-only mode.
+    pub async fn open<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::File> {
+        crate::OpenOptions::new().read(true).open(self, path).await
+    }
+
+    // URL of the mounted export, for errors raised outside of `Context::annotate`.
+    pub(crate) fn url(&self) -> &str {
+        &self.context.url
+    }
+
+    pub(crate) async fn open_with_flags<P: AsRef<Path>>(
         &self,
         path: P,
         flags: OFlag,
         mode: Mode,
     ) -> crate::Result<crate::File> {
-        let context = Arc::clone(&self.context);
-        let path = path.as_cstring()?;
+        self.context
+            .annotate("open", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
 
-        task::spawn_blocking(move || unsafe {
-            let mut file = mem::MaybeUninit::uninit();
+                if let Some(driver) = &context.driver {
+                    let file_context = Arc::clone(&context);
+                    let file_path = path.clone();
 
-            context.check_retcode(libnfs::nfs_open2(
-                context.0,
-                path.as_ptr(),
-                flags.bits(),
-                mode.bits() as i32,
-                file.as_mut_ptr(),
-            ))?;
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_open2_async(
+                                    nfs,
+                                    path.as_ptr(),
+                                    flags.bits(),
+                                    mode.bits() as i32,
+                                    cb,
+                                    data,
+                                )
+                            },
+                            move |_, file| {
+                                Ok(crate::File::new(file_context, file as _, file_path, flags))
+                            },
+                        )
+                        .await;
+                }
 
-            Ok(crate::File::new(context, file.assume_init()))
-        })
-        .await?
+                task::spawn_blocking(move || unsafe {
+                    let mut file = mem::MaybeUninit::uninit();
+
+                    context.check_retcode(libnfs::nfs_open2(
+                        context.nfs,
+                        path.as_ptr(),
+                        flags.bits(),
+                        mode.bits() as i32,
+                        file.as_mut_ptr(),
+                    ))?;
+
+                    Ok(crate::File::new(context, file.assume_init(), path, flags))
+                })
+                .await?
+            })
+            .await
+    }
+
+    /// Reads the entire contents of a file into a bytes vector.
+    pub async fn read<P: AsRef<Path>>(&self, path: P) -> crate::Result<Vec<u8>> {
+        let file = self.open(path).await?;
+        let size = file.stat().await?.len();
+        let chunk = self.context.read_max();
+
+        // The size is only a hint, the file may change while we read it
+        let mut data = Vec::with_capacity(size.try_into().unwrap_or(0));
+        let mut buf = vec![0u8; chunk];
+        loop {
+            let n = file.read_at(data.len() as u64, &mut buf).await?;
+            if n == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    pub async fn read_dir<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::ReadDir> {
+        self.context
+            .annotate("read_dir", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_ref().to_path_buf();
+                let cpath = path.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    let dir_context = Arc::clone(&context);
+
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_opendir_async(nfs, cpath.as_ptr(), cb, data)
+                            },
+                            move |_, dir| {
+                                Ok(crate::ReadDir::new(
+                                    dir_context,
+                                    crate::dir::Dir(dir as _),
+                                    path,
+                                ))
+                            },
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    let mut dir = mem::MaybeUninit::uninit();
+
+                    context.check_retcode(libnfs::nfs_opendir(
+                        context.nfs,
+                        cpath.as_ptr(),
+                        dir.as_mut_ptr(),
+                    ))?;
+
+                    Ok(crate::ReadDir::new(
+                        context,
+                        crate::dir::Dir(dir.assume_init()),
+                        path,
+                    ))
+                })
+                .await?
+            })
+            .await
+    }
+
+    /// Reads the entire contents of a file into a string.
+    pub async fn read_to_string<P: AsRef<Path>>(&self, path: P) -> crate::Result<String> {
+        into_string(self.read(path).await?)
+    }
+
+    pub async fn readlink<P: AsRef<Path>>(&self, path: P) -> crate::Result<PathBuf> {
+        self.context
+            .annotate("readlink", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_readlink_async(nfs, path.as_ptr(), cb, data)
+                            },
+                            |_, target| {
+                                let target = unsafe { CStr::from_ptr(target as *const c_char) };
+                                Ok(PathBuf::from(OsStr::from_bytes(target.to_bytes())))
+                            },
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || context.readlink_blocking(&path)).await?
+            })
+            .await
+    }
+
+    /// Removes a directory and everything inside it.
+    ///
+    /// Files are unlinked concurrently. Removal carries on past entries that fail to be
+    /// removed, and the returned error lists all of them in
+    /// [`Error::failures`](crate::Error::failures).
+    pub async fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
+        let path = path.as_ref();
+
+        // Like `std::fs::remove_dir_all`, remove a symlink itself rather than its target
+        if self.lstat(path).await?.is_symlink() {
+            return self.unlink(path).await;
+        }
+
+        let mut failed: Vec<(PathBuf, crate::Error)> = Vec::new();
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+
+        let mut walk = self.walk(path);
+        while let Some(entry) = poll_fn(|cx| Pin::new(&mut walk).poll_next(cx)).await {
+            match entry {
+                Ok(entry) if entry.file_type().is_dir() => dirs.push(entry),
+                Ok(entry) => files.push(entry.into_path()),
+                Err(e) => {
+                    let at = e.path().unwrap_or(path).to_path_buf();
+                    failed.push((at, e));
+                }
+            }
+        }
+
+        let results = join_limited(
+            files.into_iter().map(|file| async move {
+                let res = self.unlink(&file).await;
+                (file, res)
+            }),
+            REMOVE_CONCURRENCY,
+        )
+        .await;
+        failed.extend(
+            results
+                .into_iter()
+                .filter_map(|(file, res)| res.err().map(|e| (file, e))),
+        );
+
+        // Children go before their parents; a directory that still has entries because of an
+        // earlier failure fails too, which is reported as well.
+        dirs.sort_by_key(|dir| std::cmp::Reverse(dir.depth()));
+        for dir in dirs {
+            if let Err(e) = self.rmdir(dir.path()).await {
+                failed.push((dir.into_path(), e));
+            }
+        }
+
+        if failed.is_empty() {
+            return Ok(());
+        }
+
+        let msg = format!(
+            "failed to remove {} entries, first {}",
+            failed.len(),
+            failed[0].0.display()
+        );
+        Err(crate::error::failures(msg, failed).with_context(
+            "remove_dir_all",
+            Some(path),
+            &self.context.url,
+        ))
+    }
+
+    pub async fn rename<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+    ) -> crate::Result<()> {
+        self.context
+            .annotate("rename", Some(from.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let from = from.as_cstring()?;
+                let to = to.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_rename_async(nfs, from.as_ptr(), to.as_ptr(), cb, data)
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_rename(
+                        context.nfs,
+                        from.as_ptr(),
+                        to.as_ptr(),
+                    ))
+                })
+                .await?
+            })
+            .await
     }
 
     pub async fn rmdir<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
-        let context = Arc::clone(&self.context);
-        let path = path.as_cstring()?;
+        self.context
+            .annotate("rmdir", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
 
-        task::spawn_blocking(move || unsafe {
-            context.check_retcode(libnfs::nfs_rmdir(context.0, path.as_ptr()))
-        })
-        .await?
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_rmdir_async(nfs, path.as_ptr(), cb, data)
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_rmdir(context.nfs, path.as_ptr()))
+                })
+                .await?
+            })
+            .await
     }
 
-    pub async fn stat<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::Stat> {
-        let context = Arc::clone(&self.context);
-        let path = path.as_cstring()?;
+    pub async fn set_times<P: AsRef<Path>>(
+        &self,
+        path: P,
+        accessed: SystemTime,
+        modified: SystemTime,
+    ) -> crate::Result<()> {
+        self.context
+            .annotate("set_times", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
+                let mut times = [timeval(accessed)?, timeval(modified)?];
 
-        task::spawn_blocking(move || unsafe {
-            let mut stat = mem::MaybeUninit::uninit();
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_utimes_async(
+                                    nfs,
+                                    path.as_ptr(),
+                                    times.as_mut_ptr(),
+                                    cb,
+                                    data,
+                                )
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
 
-            context.check_retcode(libnfs::nfs_stat64(
-                context.0,
-                path.as_ptr(),
-                stat.as_mut_ptr(),
-            ))?;
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_utimes(
+                        context.nfs,
+                        path.as_ptr(),
+                        times.as_mut_ptr(),
+                    ))
+                })
+                .await?
+            })
+            .await
+    }
 
-            Ok(stat.assume_init())
-        })
-        .await?
+    pub async fn stat<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::Metadata> {
+        self.context
+            .annotate("stat", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_stat64_async(nfs, path.as_ptr(), cb, data)
+                            },
+                            |_, stat| Ok(unsafe { *(stat as *const libnfs::nfs_stat_64) }.into()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    let mut stat = mem::MaybeUninit::uninit();
+
+                    context.check_retcode(libnfs::nfs_stat64(
+                        context.nfs,
+                        path.as_ptr(),
+                        stat.as_mut_ptr(),
+                    ))?;
+
+                    Ok(stat.assume_init().into())
+                })
+                .await?
+            })
+            .await
+    }
+
+    pub async fn statvfs<P: AsRef<Path>>(&self, path: P) -> crate::Result<crate::FsStats> {
+        self.context
+            .annotate("statvfs", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_statvfs64_async(nfs, path.as_ptr(), cb, data)
+                            },
+                            |_, stats| {
+                                Ok(unsafe { *(stats as *const libnfs::nfs_statvfs_64) }.into())
+                            },
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    let mut stats = mem::MaybeUninit::uninit();
+
+                    context.check_retcode(libnfs::nfs_statvfs64(
+                        context.nfs,
+                        path.as_ptr(),
+                        stats.as_mut_ptr(),
+                    ))?;
+
+                    Ok(stats.assume_init().into())
+                })
+                .await?
+            })
+            .await
+    }
+
+    pub async fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        original: P,
+        link: Q,
+    ) -> crate::Result<()> {
+        self.context
+            .annotate("symlink", Some(link.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let original = original.as_cstring()?;
+                let link = link.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_symlink_async(
+                                    nfs,
+                                    original.as_ptr(),
+                                    link.as_ptr(),
+                                    cb,
+                                    data,
+                                )
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_symlink(
+                        context.nfs,
+                        original.as_ptr(),
+                        link.as_ptr(),
+                    ))
+                })
+                .await?
+            })
+            .await
+    }
+
+    pub async fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> crate::Result<()> {
+        self.context
+            .annotate("truncate", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_truncate_async(nfs, path.as_ptr(), len, cb, data)
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_truncate(context.nfs, path.as_ptr(), len))
+                })
+                .await?
+            })
+            .await
+    }
+
+    /// Returns `Ok(true)` if the path points at an existing entity, following symlinks.
+    ///
+    /// Errors other than the entity not existing, e.g. a permission error on one of its
+    /// parents, are returned instead of `false`.
+    pub async fn try_exists<P: AsRef<Path>>(&self, path: P) -> crate::Result<bool> {
+        exists(self.stat(path).await)
     }
 
     pub async fn unlink<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
-        let context = Arc::clone(&self.context);
-        let path = path.as_cstring()?;
+        self.context
+            .annotate("unlink", Some(path.as_ref()), async {
+                let context = Arc::clone(&self.context);
+                let path = path.as_cstring()?;
 
-        task::spawn_blocking(move || unsafe {
-            context.check_retcode(libnfs::nfs_unlink(context.0, path.as_ptr()))
-        })
-        .await?
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_unlink_async(nfs, path.as_ptr(), cb, data)
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_unlink(context.nfs, path.as_ptr()))
+                })
+                .await?
+            })
+            .await
+    }
+
+    pub fn walk<P: AsRef<Path>>(&self, root: P) -> crate::Walk<'_> {
+        crate::Walk::new(self, root.as_ref().to_path_buf())
+    }
+
+    /// Writes `contents` as the entire contents of a file, creating it if it doesn't exist and
+    /// truncating it if it does.
+    pub async fn write<P: AsRef<Path>, C: AsRef<[u8]>>(
+        &self,
+        path: P,
+        contents: C,
+    ) -> crate::Result<()> {
+        let file = self.create(path).await?;
+        let contents = contents.as_ref();
+        let chunk = self.context.write_max();
+
+        let mut written = 0;
+        while written < contents.len() {
+            let end = contents.len().min(written + chunk);
+            match file
+                .write_at(written as u64, &contents[written..end])
+                .await?
+            {
+                0 => {
+                    return Err(crate::error::nfs(
+                        "failed to write whole file",
+                        io::ErrorKind::WriteZero,
+                    ))
+                }
+                n => written += n,
+            }
+        }
+
+        file.close().await
     }
 }
 
-impl Drop for Client {
-    fn drop(&mut self) {
-        if !self.context.0.is_null() {
-            unsafe { libnfs::nfs_destroy_context(self.context.0) };
+// Runs `futures` with at most `limit` of them in flight, returning their outputs in completion
+// order.
+async fn join_limited<I, F>(futures: I, limit: usize) -> Vec<F::Output>
+where
+    I: IntoIterator<Item = F>,
+    F: Future,
+{
+    let mut futures = futures.into_iter();
+    let mut in_flight: Vec<Pin<Box<F>>> = Vec::new();
+    let mut outputs = Vec::new();
+
+    loop {
+        while in_flight.len() < limit {
+            match futures.next() {
+                Some(fut) => in_flight.push(Box::pin(fut)),
+                None => break,
+            }
         }
+        if in_flight.is_empty() {
+            return outputs;
+        }
+
+        poll_fn(|cx| {
+            let mut progress = false;
+            let mut i = 0;
+            while i < in_flight.len() {
+                match in_flight[i].as_mut().poll(cx) {
+                    Poll::Ready(output) => {
+                        drop(in_flight.swap_remove(i));
+                        outputs.push(output);
+                        progress = true;
+                    }
+                    Poll::Pending => i += 1,
+                }
+            }
+
+            if progress {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+    }
+}
+
+pub(crate) fn timeval(time: SystemTime) -> crate::Result<libnfs::timeval> {
+    let since_epoch = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| crate::error::nfs("time is before UNIX epoch", io::ErrorKind::InvalidInput))?;
+
+    Ok(libnfs::timeval {
+        tv_sec: since_epoch.as_secs() as _,
+        tv_usec: since_epoch.subsec_micros() as _,
+    })
+}
+
+// The helpers below are shared by the async and the blocking clients.
+
+// Tells whether `dir`, an ancestor of the path passed to `create_dir_all`, is an existing
+// directory to create the missing components under.
+pub(crate) fn is_existing_dir(
+    context: &Context,
+    dir: &Path,
+    stat: crate::Result<crate::Metadata>,
+) -> crate::Result<bool> {
+    match stat {
+        Ok(md) if md.is_dir() => Ok(true),
+        Ok(_) => Err(
+            crate::error::nfs("not a directory", Errno::ENOTDIR).with_context(
+                "create_dir_all",
+                Some(dir),
+                &context.url,
+            ),
+        ),
+        Err(e) if e.is_not_found() => Ok(false),
+        Err(e) => Err(e),
     }
 }
+
+// Tells whether `create_dir_all` failed to create a component because someone else just did.
+pub(crate) fn is_already_exists(e: &crate::Error) -> bool {
+    e.into_io().kind() == io::ErrorKind::AlreadyExists
+}
+
+// Maps the result of stat-ing a path to the result of `try_exists`.
+pub(crate) fn exists(stat: crate::Result<crate::Metadata>) -> crate::Result<bool> {
+    match stat {
+        Ok(_) => Ok(true),
+        Err(e) if e.is_not_found() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// Converts the contents of a file read by `read_to_string`.
+pub(crate) fn into_string(data: Vec<u8>) -> crate::Result<String> {
+    String::from_utf8(data).map_err(|e| {
+        crate::error::nfs(
+            "file did not contain valid UTF-8",
+            io::Error::new(io::ErrorKind::InvalidData, e),
+        )
+    })
+}
+
+// Error returned by operations on a client that has been unmounted.
+fn not_mounted() -> crate::Error {
+    crate::error::nfs("export is not mounted", Errno::ENOTCONN)
+}
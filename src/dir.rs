@@ -0,0 +1,149 @@
+use crate::FileType;
+use futures_core::Stream;
+use libnfs_sys as libnfs;
+use std::{
+    ffi::{CStr, OsStr, OsString},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+pub(crate) struct Dir(pub(crate) *mut libnfs::nfsdir);
+
+unsafe impl Send for Dir {}
+unsafe impl Sync for Dir {}
+
+/// A stream over the entries of a directory.
+///
+/// Returned by [`Client::read_dir`](crate::Client::read_dir). The `.` and `..` entries are
+/// skipped.
+pub struct ReadDir {
+    context: Arc<crate::client::Context>,
+    dir: Dir,
+    path: PathBuf,
+}
+
+/// An entry returned by the [`ReadDir`] stream.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    path: PathBuf,
+    file_type: FileType,
+    metadata: crate::Metadata,
+}
+
+impl ReadDir {
+    pub(crate) fn new(context: Arc<crate::client::Context>, dir: Dir, path: PathBuf) -> ReadDir {
+        ReadDir { context, dir, path }
+    }
+
+    pub(crate) fn next_entry(&mut self) -> Option<DirEntry> {
+        // libnfs fetches the whole directory in `nfs_opendir`, so `nfs_readdir` only walks the
+        // in-memory list and never blocks.
+        loop {
+            let ent = unsafe { libnfs::nfs_readdir(self.context.nfs, self.dir.0) };
+            if ent.is_null() {
+                return None;
+            }
+
+            let ent = unsafe { &*ent };
+            let name = OsStr::from_bytes(unsafe { CStr::from_ptr(ent.name) }.to_bytes());
+            if name == "." || name == ".." {
+                continue;
+            }
+
+            return Some(DirEntry::new(self.path.join(name), ent));
+        }
+    }
+}
+
+impl Stream for ReadDir {
+    type Item = DirEntry;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.get_mut().next_entry())
+    }
+}
+
+impl Drop for ReadDir {
+    fn drop(&mut self) {
+        if !self.context.nfs.is_null() && !self.dir.0.is_null() {
+            match self.context.driver {
+                Some(ref driver) => {
+                    let dir = self.dir.0 as usize;
+                    let _ = driver.submit(move |nfs| unsafe {
+                        libnfs::nfs_closedir(nfs, dir as *mut libnfs::nfsdir);
+                    });
+                }
+                None => unsafe { libnfs::nfs_closedir(self.context.nfs, self.dir.0) },
+            }
+        }
+    }
+}
+
+impl DirEntry {
+    fn new(path: PathBuf, ent: &libnfs::nfsdirent) -> DirEntry {
+        let file_type = FileType::from_nf3(ent.type_);
+
+        DirEntry {
+            path,
+            file_type,
+            metadata: crate::Metadata::from(libnfs::nfs_stat_64 {
+                nfs_dev: ent.dev,
+                nfs_ino: ent.inode,
+                // Not every libnfs version fills in the file type bits of the mode
+                nfs_mode: (ent.mode as u64 & 0o7777) | file_type.to_mode(),
+                nfs_nlink: ent.nlink as u64,
+                nfs_uid: ent.uid as u64,
+                nfs_gid: ent.gid as u64,
+                nfs_rdev: ent.rdev,
+                nfs_size: ent.size,
+                nfs_blksize: ent.blksize,
+                nfs_blocks: ent.blocks,
+                nfs_atime: ent.atime.tv_sec as u64,
+                nfs_mtime: ent.mtime.tv_sec as u64,
+                nfs_ctime: ent.ctime.tv_sec as u64,
+                nfs_atime_nsec: ent.atime_nsec as u64,
+                nfs_mtime_nsec: ent.mtime_nsec as u64,
+                nfs_ctime_nsec: ent.ctime_nsec as u64,
+                nfs_used: ent.used,
+            }),
+        }
+    }
+
+    /// Returns the full path to the entry, i.e. the directory passed to `read_dir` joined with
+    /// the entry's file name.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Returns the bare file name of the entry.
+    pub fn file_name(&self) -> OsString {
+        self.path
+            .file_name()
+            .map(OsStr::to_os_string)
+            .unwrap_or_default()
+    }
+
+    /// Returns the inode number of the entry.
+    pub fn ino(&self) -> u64 {
+        self.metadata.ino()
+    }
+
+    /// Returns the type of the entry.
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// Returns the metadata of the entry as reported by the server during directory listing.
+    pub fn metadata(&self) -> crate::Metadata {
+        self.metadata
+    }
+}
+
+impl AsRef<Path> for DirEntry {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
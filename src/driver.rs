@@ -0,0 +1,243 @@
+use crate::ToStringLossy;
+use libnfs_sys as libnfs;
+use nix::poll::{poll, PollFd, PollFlags};
+use std::{
+    ffi::{c_int, c_void},
+    io,
+    os::fd::{AsRawFd, BorrowedFd, RawFd},
+    time::Duration,
+};
+use tokio::{
+    io::{
+        unix::{AsyncFd, AsyncFdReadyGuard},
+        Interest,
+    },
+    sync::{mpsc, oneshot},
+    time::{self, MissedTickBehavior},
+};
+
+// libnfs checks for timed out RPCs only when serviced, so wake up at least this often.
+const TICK: Duration = Duration::from_millis(100);
+
+type Job = Box<dyn FnOnce(*mut libnfs::nfs_context) + Send>;
+type Completion = Box<dyn FnOnce(crate::Result<(i32, *mut c_void)>) + Send>;
+
+enum Message {
+    Job(Job),
+    Shutdown,
+}
+
+/// Handle to a task that drives a libnfs context from the tokio reactor.
+///
+/// The task exclusively owns the context: every operation is submitted to it as a job that
+/// issues an `nfs_*_async` call, and the completion callback is run from `nfs_service` when
+/// the socket becomes ready.
+pub(crate) struct Driver {
+    jobs: mpsc::UnboundedSender<Message>,
+}
+
+struct Nfs(*mut libnfs::nfs_context);
+
+unsafe impl Send for Nfs {}
+
+struct Fd(RawFd);
+
+impl AsRawFd for Fd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Driver {
+    pub(crate) fn start(nfs: *mut libnfs::nfs_context) -> Driver {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(Nfs(nfs), rx));
+
+        Driver { jobs: tx }
+    }
+
+    /// Submits an asynchronous operation and waits for its completion.
+    ///
+    /// `submit` is given the context, the callback and its private data, and must return the
+    /// result of the `nfs_*_async` call. `complete` is called from the callback with the status
+    /// and the data pointer of a successful operation; everything it needs to outlive the
+    /// request (e.g. the buffer of a write, or the file handle it is on) should be moved into it.
+    pub(crate) async fn call<T, S, C>(&self, submit: S, complete: C) -> crate::Result<T>
+    where
+        T: Send + 'static,
+        S: FnOnce(*mut libnfs::nfs_context, libnfs::nfs_cb, *mut c_void) -> c_int + Send + 'static,
+        C: FnOnce(i32, *mut c_void) -> crate::Result<T> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.spawn(submit, move |res| {
+            let _ = tx.send(res.and_then(|(status, data)| complete(status, data)));
+        })?;
+
+        rx.await.map_err(|_| stopped())?
+    }
+
+    /// Submits an asynchronous operation without waiting for it.
+    ///
+    /// Same as [`call`](Driver::call), except that `complete` is given the result of the
+    /// operation as is. It isn't called at all if the driver stops before issuing the operation.
+    pub(crate) fn spawn<S, C>(&self, submit: S, complete: C) -> crate::Result<()>
+    where
+        S: FnOnce(*mut libnfs::nfs_context, libnfs::nfs_cb, *mut c_void) -> c_int + Send + 'static,
+        C: FnOnce(crate::Result<(i32, *mut c_void)>) + Send + 'static,
+    {
+        let completion: Completion = Box::new(complete);
+
+        self.submit(move |nfs| unsafe {
+            let private_data = Box::into_raw(Box::new(completion));
+
+            let ret = submit(nfs, Some(callback), private_data as *mut c_void);
+            if ret < 0 {
+                let completion = Box::from_raw(private_data);
+                completion(Err(error(
+                    libnfs::nfs_get_error(nfs).to_string_lossy(),
+                    ret,
+                )));
+            }
+        })
+    }
+
+    /// Submits a job to be run on the driver without waiting for it.
+    pub(crate) fn submit<F>(&self, job: F) -> crate::Result<()>
+    where
+        F: FnOnce(*mut libnfs::nfs_context) + Send + 'static,
+    {
+        self.jobs
+            .send(Message::Job(Box::new(job)))
+            .map_err(|_| stopped())
+    }
+
+    /// Stops the driver and destroys the context once all previously submitted jobs have been
+    /// issued.
+    pub(crate) fn shutdown(&self) {
+        let _ = self.jobs.send(Message::Shutdown);
+    }
+}
+
+unsafe extern "C" fn callback(
+    err: c_int,
+    _nfs: *mut libnfs::nfs_context,
+    data: *mut c_void,
+    private_data: *mut c_void,
+) {
+    let completion = Box::from_raw(private_data as *mut Completion);
+
+    if err < 0 {
+        let msg = if data.is_null() {
+            String::new()
+        } else {
+            (data as *const i8).to_string_lossy()
+        };
+        completion(Err(error(msg, err)));
+    } else {
+        completion(Ok((err, data)));
+    }
+}
+
+fn error(msg: String, err: i32) -> crate::Error {
+    crate::error::nfs(msg, io::Error::from_raw_os_error(-err))
+}
+
+fn stopped() -> crate::Error {
+    crate::error::nfs("libnfs driver is not running", io::ErrorKind::NotConnected)
+}
+
+async fn run(nfs: Nfs, mut jobs: mpsc::UnboundedReceiver<Message>) {
+    let mut fd: Option<AsyncFd<Fd>> = None;
+    // Set after servicing the socket failed, until the next tick gets libnfs to reconnect.
+    let mut stale = false;
+    let mut tick = time::interval(TICK);
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        // The socket changes on (re)connect, possibly keeping the same number, so re-register it
+        // whenever it does or servicing it failed.
+        let raw = unsafe { libnfs::nfs_get_fd(nfs.0) };
+        if !stale && fd.as_ref().map(|fd| fd.get_ref().0) != Some(raw) {
+            // Deregister the old socket first, its number may belong to the new one already
+            drop(fd.take());
+            fd = if raw < 0 {
+                None
+            } else {
+                AsyncFd::with_interest(Fd(raw), Interest::READABLE | Interest::WRITABLE).ok()
+            };
+        }
+
+        let events = PollFlags::from_bits_truncate(unsafe { libnfs::nfs_which_events(nfs.0) } as _);
+        let interest = if events.contains(PollFlags::POLLOUT) {
+            Interest::READABLE | Interest::WRITABLE
+        } else {
+            Interest::READABLE
+        };
+
+        tokio::select! {
+            msg = jobs.recv() => match msg {
+                Some(Message::Job(job)) => job(nfs.0),
+                Some(Message::Shutdown) | None => {
+                    drop(fd.take());
+                    unsafe { libnfs::nfs_destroy_context(nfs.0) };
+                    return;
+                }
+            },
+            guard = ready(&fd, interest) => {
+                stale = match guard {
+                    Ok(guard) => service(nfs.0, raw, guard),
+                    Err(_) => true,
+                };
+            }
+            _ = tick.tick() => {
+                stale = unsafe { libnfs::nfs_service(nfs.0, 0) } < 0;
+            }
+        }
+        if stale {
+            fd = None;
+        }
+    }
+}
+
+async fn ready(
+    fd: &Option<AsyncFd<Fd>>,
+    interest: Interest,
+) -> io::Result<AsyncFdReadyGuard<'_, Fd>> {
+    match fd {
+        Some(fd) => fd.ready(interest).await,
+        None => std::future::pending().await,
+    }
+}
+
+// Returns whether the socket has to be registered again.
+fn service(
+    nfs: *mut libnfs::nfs_context,
+    raw: RawFd,
+    mut guard: AsyncFdReadyGuard<'_, Fd>,
+) -> bool {
+    // Readiness reported by the reactor is edge-triggered, so keep servicing the socket until it
+    // has nothing more for us and only then clear it.
+    loop {
+        let events = PollFlags::from_bits_truncate(unsafe { libnfs::nfs_which_events(nfs) } as _);
+        let fd = unsafe { BorrowedFd::borrow_raw(raw) };
+        let mut fds = [PollFd::new(&fd, events)];
+
+        let revents = match poll(&mut fds, 0) {
+            Ok(n) if n > 0 => fds[0].revents().unwrap_or(PollFlags::empty()),
+            _ => PollFlags::empty(),
+        };
+        if revents.is_empty() {
+            guard.clear_ready();
+            return false;
+        }
+
+        // On failure or reconnect fall back to the periodic tick to avoid spinning on a dead
+        // socket.
+        if unsafe { libnfs::nfs_service(nfs, revents.bits() as i32) } < 0
+            || unsafe { libnfs::nfs_get_fd(nfs) } != raw
+        {
+            guard.clear_ready();
+            return true;
+        }
+    }
+}
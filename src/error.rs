@@ -1,4 +1,9 @@
-use std::{error::Error as StdError, fmt, io};
+use nix::errno::Errno;
+use std::{
+    error::Error as StdError,
+    fmt, io,
+    path::{Path, PathBuf},
+};
 use tokio::task;
 
 /// A `Result` alias where the `Err` is `nfs::Error`
@@ -13,7 +18,29 @@ type BoxError = Box<dyn StdError + Send + Sync>;
 
 struct Inner {
     kind: Kind,
+    status: Option<NfsStatus>,
     source: Option<BoxError>,
+
+    operation: Option<&'static str>,
+    path: Option<PathBuf>,
+    offset: Option<u64>,
+    url: Option<String>,
+
+    failures: Vec<(PathBuf, Error)>,
+}
+
+/// The category of an [`Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The server URL is invalid.
+    Url,
+    /// An NFS operation failed, either on the server or in libnfs.
+    Nfs,
+    /// A blocking task failed to complete, e.g. because the runtime is shutting down.
+    Runtime,
+    /// The server didn't respond within the configured timeout.
+    Timeout,
 }
 
 impl Error {
@@ -24,11 +51,118 @@ impl Error {
         Error {
             inner: Box::new(Inner {
                 kind,
+                status: None,
                 source: source.map(Into::into),
+                operation: None,
+                path: None,
+                offset: None,
+                url: None,
+                failures: Vec::new(),
             }),
         }
     }
 
+    // Records the failed operation, unless a nested operation that failed first already did.
+    pub(crate) fn with_context(
+        mut self,
+        operation: &'static str,
+        path: Option<&Path>,
+        url: &str,
+    ) -> Error {
+        if self.inner.operation.is_none() {
+            self.inner.operation = Some(operation);
+            self.inner.path = path.map(Path::to_path_buf);
+        }
+        if self.inner.url.is_none() {
+            self.inner.url = Some(url.to_string());
+        }
+        self
+    }
+
+    pub(crate) fn with_offset(mut self, offset: u64) -> Error {
+        self.inner.offset.get_or_insert(offset);
+        self
+    }
+
+    /// Returns the category of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self.inner.kind {
+            Kind::Url => ErrorKind::Url,
+            Kind::Nfs(_) => ErrorKind::Nfs,
+            Kind::Runtime => ErrorKind::Runtime,
+            Kind::Timeout(_) => ErrorKind::Timeout,
+        }
+    }
+
+    /// Returns the status reported by the server, if the error is an NFS error response.
+    ///
+    /// Errors raised locally by libnfs or by this crate, e.g. an invalid path, don't have a
+    /// status.
+    pub fn nfs_status(&self) -> Option<NfsStatus> {
+        self.inner.status
+    }
+
+    /// Returns `true` if the error means that the file or directory doesn't exist.
+    pub fn is_not_found(&self) -> bool {
+        self.inner.status == Some(NfsStatus::NoEnt) || self.errno() == Some(Errno::ENOENT)
+    }
+
+    /// Returns `true` if the server denied access to the file or directory.
+    pub fn is_permission_denied(&self) -> bool {
+        matches!(self.inner.status, Some(NfsStatus::Perm | NfsStatus::Access))
+            || matches!(self.errno(), Some(Errno::EPERM | Errno::EACCES))
+    }
+
+    /// Returns `true` if the file handle is no longer valid, e.g. because the file was removed
+    /// by another client. The file has to be looked up and opened again.
+    pub fn is_stale(&self) -> bool {
+        matches!(
+            self.inner.status,
+            Some(NfsStatus::Stale | NfsStatus::FhExpired)
+        ) || self.errno() == Some(Errno::ESTALE)
+    }
+
+    /// Returns the name of the failed operation, e.g. `"stat"`, if the error comes from one.
+    pub fn operation(&self) -> Option<&str> {
+        self.inner.operation
+    }
+
+    /// Returns the path the failed operation was working on. For file operations, this is the
+    /// path the file was opened with.
+    pub fn path(&self) -> Option<&Path> {
+        self.inner.path.as_deref()
+    }
+
+    /// Returns the offset in the file at which a failed read or write started.
+    pub fn offset(&self) -> Option<u64> {
+        self.inner.offset
+    }
+
+    /// Returns the URL of the server the failed operation was sent to.
+    pub fn url(&self) -> Option<&str> {
+        self.inner.url.as_deref()
+    }
+
+    /// Returns the entries that failed, with their own errors, when an operation on a whole tree
+    /// like [`Client::remove_dir_all`](crate::Client::remove_dir_all) carried on past them. The
+    /// error itself reports the status and errno of the first one.
+    pub fn failures(&self) -> &[(PathBuf, Error)] {
+        &self.inner.failures
+    }
+
+    /// Returns the errno libnfs reported the failure with, if any.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        self.inner
+            .source
+            .as_ref()
+            .and_then(|source| source.downcast_ref::<io::Error>())
+            .and_then(io::Error::raw_os_error)
+    }
+
+    fn errno(&self) -> Option<Errno> {
+        self.raw_os_error().map(Errno::from_i32)
+    }
+
     pub fn into_io(&self) -> io::Error {
         self.inner
             .source
@@ -40,6 +174,14 @@ impl Error {
             })
             .unwrap_or_else(|| io::Error::new(io::ErrorKind::Other, self.to_string()))
     }
+
+    fn io_kind(&self) -> io::ErrorKind {
+        self.inner
+            .source
+            .as_ref()
+            .and_then(|source| source.downcast_ref::<io::Error>())
+            .map_or(io::ErrorKind::Other, io::Error::kind)
+    }
 }
 
 impl fmt::Debug for Error {
@@ -47,6 +189,21 @@ impl fmt::Debug for Error {
         let mut builder = f.debug_struct("nfs::Error");
 
         builder.field("kind", &self.inner.kind);
+        if let Some(operation) = self.inner.operation {
+            builder.field("operation", &operation);
+        }
+        if let Some(ref path) = self.inner.path {
+            builder.field("path", path);
+        }
+        if let Some(offset) = self.inner.offset {
+            builder.field("offset", &offset);
+        }
+        if let Some(ref url) = self.inner.url {
+            builder.field("url", url);
+        }
+        if !self.inner.failures.is_empty() {
+            builder.field("failures", &self.inner.failures);
+        }
         if let Some(ref source) = self.inner.source {
             builder.field("source", source);
         }
@@ -57,10 +214,25 @@ impl fmt::Debug for Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(operation) = self.inner.operation {
+            f.write_str(operation)?;
+            if let Some(ref path) = self.inner.path {
+                write!(f, " {}", path.display())?;
+            }
+            if let Some(offset) = self.inner.offset {
+                write!(f, " at offset {offset}")?;
+            }
+            if let Some(ref url) = self.inner.url {
+                write!(f, " on {url}")?;
+            }
+            f.write_str(": ")?;
+        }
+
         match &self.inner.kind {
             Kind::Url => f.write_str("URL error")?,
             Kind::Nfs(msg) => write!(f, "NFS error: {msg}")?,
             Kind::Runtime => f.write_str("runtime error")?,
+            Kind::Timeout(msg) => write!(f, "NFS timeout: {msg}")?,
         };
 
         if let Some(e) = &self.inner.source {
@@ -77,6 +249,15 @@ impl StdError for Error {
     }
 }
 
+/// Converts to an `io::Error` of the matching kind, which keeps the original error as its inner
+/// error, so that it can be recovered with [`io::Error::get_ref`] or [`io::Error::into_inner`]
+/// and `downcast`.
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        io::Error::new(e.io_kind(), e)
+    }
+}
+
 impl From<task::JoinError> for Error {
     fn from(e: task::JoinError) -> Self {
         Error::new(Kind::Runtime, Some(e))
@@ -87,8 +268,44 @@ pub(crate) fn url<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Url, Some(e))
 }
 
+// Recovers an error converted to an `io::Error` by `From<Error>`.
+pub(crate) fn from_io(e: io::Error) -> Error {
+    match e.get_ref().map(|inner| inner.is::<Error>()) {
+        Some(true) => *e.into_inner().unwrap().downcast::<Error>().unwrap(),
+        _ => nfs("I/O error", e),
+    }
+}
+
 pub(crate) fn nfs<M: Into<String>, E: Into<io::Error>>(msg: M, e: E) -> Error {
-    Error::new(Kind::Nfs(msg.into()), Some(e.into()))
+    let msg = msg.into();
+    let e = e.into();
+
+    let status = NfsStatus::from_message(&msg);
+    let kind = if e.raw_os_error() == Some(Errno::ETIMEDOUT as i32) {
+        Kind::Timeout(msg)
+    } else {
+        Kind::Nfs(msg)
+    };
+
+    let mut err = Error::new(kind, Some(e));
+    err.inner.status = status;
+    err
+}
+
+// Aggregates the errors of the entries an operation failed on, taking the errno and status of
+// the first one.
+pub(crate) fn failures<M: Into<String>>(msg: M, failures: Vec<(PathBuf, Error)>) -> Error {
+    let first = &failures[0].1;
+    let source = match first.raw_os_error() {
+        Some(errno) => io::Error::from_raw_os_error(errno),
+        None => first.io_kind().into(),
+    };
+    let status = first.inner.status;
+
+    let mut err = nfs(msg, source);
+    err.inner.status = status;
+    err.inner.failures = failures;
+    err
 }
 
 #[derive(Debug)]
@@ -96,6 +313,197 @@ pub(crate) enum Kind {
     Url,
     Nfs(String),
     Runtime,
+    Timeout(String),
+}
+
+macro_rules! nfs_status {
+    ($($(#[$doc:meta])* $variant:ident = $code:literal, [$($name:literal),+];)+) => {
+        /// A status code returned by an NFSv3 or NFSv4 server.
+        ///
+        /// NFSv4 status codes are a superset of NFSv3 ones, so both versions share the same
+        /// enum. The discriminants are the protocol values.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[non_exhaustive]
+        #[repr(u32)]
+        pub enum NfsStatus {
+            $($(#[$doc])* $variant = $code,)+
+        }
+
+        impl NfsStatus {
+            /// Returns the status with the given protocol value.
+            pub fn from_code(code: u32) -> Option<NfsStatus> {
+                match code {
+                    $($code => Some(NfsStatus::$variant),)+
+                    _ => None,
+                }
+            }
+
+            /// Returns the protocol value of the status.
+            pub fn code(self) -> u32 {
+                self as u32
+            }
+
+            // Looks up a status by its name without the `NFS3ERR_`/`NFS4ERR_` prefix.
+            fn from_name(name: &str) -> Option<NfsStatus> {
+                match name {
+                    $($($name)|+ => Some(NfsStatus::$variant),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+nfs_status! {
+    /// Not owner.
+    Perm = 1, ["PERM"];
+    /// No such file or directory.
+    NoEnt = 2, ["NOENT"];
+    /// I/O error.
+    Io = 5, ["IO"];
+    /// No such device or address.
+    Nxio = 6, ["NXIO"];
+    /// Permission denied.
+    Access = 13, ["ACCES", "ACCESS"];
+    /// File exists.
+    Exist = 17, ["EXIST"];
+    /// Cross-device hard link.
+    Xdev = 18, ["XDEV"];
+    /// No such device.
+    NoDev = 19, ["NODEV"];
+    /// Not a directory.
+    NotDir = 20, ["NOTDIR"];
+    /// Is a directory.
+    IsDir = 21, ["ISDIR"];
+    /// Invalid argument.
+    Inval = 22, ["INVAL"];
+    /// File too large.
+    FBig = 27, ["FBIG"];
+    /// No space left on device.
+    NoSpc = 28, ["NOSPC"];
+    /// Read-only file system.
+    Rofs = 30, ["ROFS"];
+    /// Too many hard links.
+    Mlink = 31, ["MLINK"];
+    /// File name too long.
+    NameTooLong = 63, ["NAMETOOLONG"];
+    /// Directory not empty.
+    NotEmpty = 66, ["NOTEMPTY"];
+    /// Quota exceeded.
+    DQuot = 69, ["DQUOT"];
+    /// Stale file handle.
+    Stale = 70, ["STALE"];
+    /// Too many levels of remote in path.
+    Remote = 71, ["REMOTE"];
+    /// Illegal file handle.
+    BadHandle = 10001, ["BADHANDLE"];
+    /// Attributes changed during SETATTR.
+    NotSync = 10002, ["NOT_SYNC"];
+    /// READDIR cookie is stale.
+    BadCookie = 10003, ["BAD_COOKIE"];
+    /// Operation not supported.
+    NotSupp = 10004, ["NOTSUPP"];
+    /// Buffer or request is too small.
+    TooSmall = 10005, ["TOOSMALL"];
+    /// Server error that doesn't map to any other status.
+    ServerFault = 10006, ["SERVERFAULT"];
+    /// Object of an unsupported type.
+    BadType = 10007, ["BADTYPE"];
+    /// The server is busy, retry later. `NFS3ERR_JUKEBOX` in NFSv3.
+    Delay = 10008, ["JUKEBOX", "DELAY"];
+    /// Attributes are the same as the ones passed to VERIFY.
+    Same = 10009, ["SAME"];
+    /// Lock is unavailable.
+    Denied = 10010, ["DENIED"];
+    /// Lock lease has expired.
+    Expired = 10011, ["EXPIRED"];
+    /// I/O failed due to a lock.
+    Locked = 10012, ["LOCKED"];
+    /// The server is in its grace period.
+    Grace = 10013, ["GRACE"];
+    /// Volatile file handle has expired.
+    FhExpired = 10014, ["FHEXPIRED"];
+    /// Share reservation denied.
+    ShareDenied = 10015, ["SHARE_DENIED"];
+    /// Wrong security flavor.
+    WrongSec = 10016, ["WRONGSEC"];
+    /// Client ID is in use.
+    ClidInUse = 10017, ["CLID_INUSE"];
+    /// The server ran out of resources.
+    Resource = 10018, ["RESOURCE"];
+    /// The file system has moved.
+    Moved = 10019, ["MOVED"];
+    /// No current file handle.
+    NoFileHandle = 10020, ["NOFILEHANDLE"];
+    /// Minor version is not supported.
+    MinorVersMismatch = 10021, ["MINOR_VERS_MISMATCH"];
+    /// Client ID is not known to the server, e.g. after its lease expired or the server rebooted.
+    StaleClientId = 10022, ["STALE_CLIENTID"];
+    /// State ID was issued by an earlier instance of the server, before it rebooted.
+    StaleStateId = 10023, ["STALE_STATEID"];
+    /// State ID is out of date.
+    OldStateId = 10024, ["OLD_STATEID"];
+    /// Invalid state ID.
+    BadStateId = 10025, ["BAD_STATEID"];
+    /// Invalid sequence ID.
+    BadSeqId = 10026, ["BAD_SEQID"];
+    /// Attributes are not the same as the ones passed to NVERIFY.
+    NotSame = 10027, ["NOT_SAME"];
+    /// Lock range is not supported.
+    LockRange = 10028, ["LOCK_RANGE"];
+    /// Object is a symbolic link.
+    Symlink = 10029, ["SYMLINK"];
+    /// No saved file handle.
+    RestoreFh = 10030, ["RESTOREFH"];
+    /// Some file system has moved.
+    LeaseMoved = 10031, ["LEASE_MOVED"];
+    /// Attribute is not supported.
+    AttrNotSupp = 10032, ["ATTRNOTSUPP"];
+    /// Reclaim outside of the grace period.
+    NoGrace = 10033, ["NO_GRACE"];
+    /// Reclaim error at the server.
+    ReclaimBad = 10034, ["RECLAIM_BAD"];
+    /// Conflict on reclaim.
+    ReclaimConflict = 10035, ["RECLAIM_CONFLICT"];
+    /// Request can't be decoded.
+    BadXdr = 10036, ["BADXDR"];
+    /// File still has locks.
+    LocksHeld = 10037, ["LOCKS_HELD"];
+    /// Operation conflicts with the open mode.
+    OpenMode = 10038, ["OPENMODE"];
+    /// Owner can't be mapped.
+    BadOwner = 10039, ["BADOWNER"];
+    /// Invalid character in a name.
+    BadChar = 10040, ["BADCHAR"];
+    /// Invalid name.
+    BadName = 10041, ["BADNAME"];
+    /// Lock range is out of bounds.
+    BadRange = 10042, ["BAD_RANGE"];
+    /// Locking is not supported.
+    LockNotSupp = 10043, ["LOCK_NOTSUPP"];
+    /// Illegal operation.
+    OpIllegal = 10044, ["OP_ILLEGAL"];
+    /// Lock would deadlock.
+    Deadlock = 10045, ["DEADLOCK"];
+    /// File is open.
+    FileOpen = 10046, ["FILE_OPEN"];
+    /// State was revoked by the administrator.
+    AdminRevoked = 10047, ["ADMIN_REVOKED"];
+    /// Callback path is down.
+    CbPathDown = 10048, ["CB_PATH_DOWN"];
+}
+
+impl NfsStatus {
+    // libnfs reports server errors as e.g. "NFS: LOOKUP of /foo failed with NFS3ERR_NOENT(-2)".
+    fn from_message(msg: &str) -> Option<NfsStatus> {
+        ["NFS3ERR_", "NFS4ERR_"].iter().find_map(|prefix| {
+            let (_, rest) = msg.split_once(prefix)?;
+            let end = rest
+                .find(|c: char| !(c.is_ascii_uppercase() || c == '_'))
+                .unwrap_or(rest.len());
+            NfsStatus::from_name(&rest[..end])
+        })
+    }
 }
 
 #[cfg(test)]
@@ -114,6 +522,97 @@ mod tests {
     #[test]
     fn non_io_error() {
         let err = url("invalid scheme");
-        assert_eq!(err.into_io().kind(), io::ErrorKind::Other)
+        assert_eq!(err.into_io().kind(), io::ErrorKind::Other);
+        assert_eq!(err.kind(), ErrorKind::Url);
+        assert_eq!(err.nfs_status(), None);
+    }
+
+    #[test]
+    fn nfs_status() {
+        let err = nfs(
+            "NFS: LOOKUP of /foo failed with NFS3ERR_NOENT(-2)",
+            io::Error::from_raw_os_error(Errno::ENOENT as i32),
+        );
+        assert_eq!(err.kind(), ErrorKind::Nfs);
+        assert_eq!(err.nfs_status(), Some(NfsStatus::NoEnt));
+        assert!(err.is_not_found());
+        assert!(!err.is_stale());
+
+        let err = nfs(
+            "NFS4: WRITE (path /foo) failed with NFS4ERR_STALE_STATEID(-116)",
+            io::Error::from_raw_os_error(Errno::ESTALE as i32),
+        );
+        assert_eq!(err.nfs_status(), Some(NfsStatus::StaleStateId));
+        assert!(err.is_stale());
+
+        let err = nfs(
+            "NFS: READ failed with NFS3ERR_JUKEBOX(-11)",
+            io::Error::from_raw_os_error(Errno::EAGAIN as i32),
+        );
+        assert_eq!(err.nfs_status(), Some(NfsStatus::Delay));
+        assert_eq!(NfsStatus::from_code(10008), Some(NfsStatus::Delay));
+        assert_eq!(NfsStatus::Delay.code(), 10008);
+    }
+
+    #[test]
+    fn operation_context() {
+        let err = nfs("NFS: READ failed", Errno::ENOENT)
+            .with_offset(4096)
+            .with_context(
+                "read_at",
+                Some(Path::new("/data/file")),
+                "nfs://server/export",
+            )
+            .with_context("read", Some(Path::new("/data")), "nfs://server/export");
+        assert_eq!(err.operation(), Some("read_at"));
+        assert_eq!(err.path(), Some(Path::new("/data/file")));
+        assert_eq!(err.offset(), Some(4096));
+        assert_eq!(err.url(), Some("nfs://server/export"));
+        assert!(err
+            .to_string()
+            .starts_with("read_at /data/file at offset 4096 on nfs://server/export: NFS error:"));
+
+        let io: io::Error = err.into();
+        assert_eq!(io.kind(), io::ErrorKind::NotFound);
+        let err = from_io(io);
+        assert_eq!(err.operation(), Some("read_at"));
+        assert_eq!(err.raw_os_error(), Some(Errno::ENOENT as i32));
+    }
+
+    #[test]
+    fn aggregated_failures() {
+        let failed = ["/dir/a", "/dir/b"]
+            .into_iter()
+            .map(|path| {
+                let err = nfs(
+                    "NFS: REMOVE failed with NFS3ERR_ACCES(-13)",
+                    io::Error::from_raw_os_error(Errno::EACCES as i32),
+                );
+                (PathBuf::from(path), err)
+            })
+            .collect();
+        let err = failures("failed to remove 2 entries", failed).with_context(
+            "remove_dir_all",
+            Some(Path::new("/dir")),
+            "nfs://server/export",
+        );
+        assert_eq!(err.nfs_status(), Some(NfsStatus::Access));
+        assert_eq!(err.raw_os_error(), Some(Errno::EACCES as i32));
+        assert!(err.is_permission_denied());
+        assert_eq!(err.operation(), Some("remove_dir_all"));
+
+        let paths = err.failures().iter().map(|(path, _)| path.as_path());
+        assert!(paths.eq([Path::new("/dir/a"), Path::new("/dir/b")]));
+        assert!(err.failures()[1].1.is_permission_denied());
+    }
+
+    #[test]
+    fn local_errors() {
+        let err = nfs("invalid path", Errno::EACCES);
+        assert_eq!(err.nfs_status(), None);
+        assert!(err.is_permission_denied());
+
+        let err = nfs("timed out", Errno::ETIMEDOUT);
+        assert_eq!(err.kind(), ErrorKind::Timeout);
     }
 }
@@ -1,23 +1,42 @@
 use libnfs_sys as libnfs;
-use nix::unistd::Whence;
-use std::ffi::c_void;
-use std::future::Future;
+use nix::{fcntl::OFlag, sys::stat::Mode, unistd::Whence};
+use std::ffi::{c_void, CString, OsStr};
+use std::future::{poll_fn, Future};
 use std::{
-    io, mem,
+    collections::VecDeque,
+    io::{self, SeekFrom},
+    mem,
+    os::unix::ffi::OsStrExt,
+    path::Path,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, PoisonError,
+    },
     task::{Context, Poll},
+    time::SystemTime,
 };
-use tokio::io::AsyncWrite;
-use tokio::sync::Mutex;
+use tokio::io::{AsyncBufRead, AsyncSeek, AsyncWrite};
+use tokio::sync::{oneshot, Mutex};
 use tokio::{
     io::{AsyncRead, ReadBuf},
+    runtime,
     task::{self, JoinHandle},
 };
 
-use crate::buf::Buf;
+use crate::{buf::Buf, client::InFlight};
 
-struct Fh(*mut libnfs::nfsfh);
+// An open file handle, closed once the file and all of its operations in flight are gone.
+//
+// A request on the async backend may outlive the future that issued it, so its completion holds
+// on to the handle until libnfs calls back.
+struct Fh {
+    raw: *mut libnfs::nfsfh,
+    cpath: CString,
+    context: Arc<crate::client::Context>,
+    // Where to report the result of closing the handle, set by `File::close`.
+    closed: std::sync::Mutex<Option<oneshot::Sender<crate::Result<()>>>>,
+}
 
 unsafe impl Send for Fh {}
 unsafe impl Sync for Fh {}
@@ -25,6 +44,13 @@ unsafe impl Sync for Fh {}
 pub struct File {
     context: Arc<crate::client::Context>,
     file: Arc<Fh>,
+    // Whether anything was written, so that `close` knows to commit.
+    dirty: AtomicBool,
+    // Opened with `O_APPEND`, which libnfs honors only for writes at the file cursor.
+    append: bool,
+
+    read_buf_size: usize,
+    write_buf_size: usize,
 
     inner: Mutex<Inner>,
 }
@@ -32,9 +58,42 @@ pub struct File {
 struct Inner {
     state: State,
 
-    last_write_err: Option<io::ErrorKind>,
+    last_write_err: Option<io::Error>,
+
+    pos: u64,
+
+    write_behind: WriteBehind,
+
+    read_ahead: ReadAhead,
 }
 
+// Positional writes issued without waiting for the previous ones, see `File::set_write_behind`.
+struct WriteBehind {
+    depth: usize,
+    // Offset of the next write. While set, the libnfs file cursor lags behind and has to be
+    // moved here before any other operation.
+    offset: Option<u64>,
+    in_flight: VecDeque<JoinHandle<io::Result<()>>>,
+}
+
+// Positional reads issued ahead of the cursor, see `File::set_read_ahead`.
+struct ReadAhead {
+    window: usize,
+    // Number of reads in a row not interrupted by a seek or a write.
+    sequential: usize,
+    // Whether the cursor position was requested to start reading ahead from it.
+    starting: bool,
+    // Offset right after the data handed over to the buffer. While set, the libnfs file cursor
+    // lags behind and reads are served from `in_flight`.
+    offset: Option<u64>,
+    // Offset of the next read to issue.
+    next: u64,
+    in_flight: VecDeque<(usize, JoinHandle<io::Result<Vec<u8>>>)>,
+}
+
+// Number of sequential reads after which read-ahead kicks in.
+const SEQUENTIAL_READS: usize = 2;
+
 enum State {
     Idle(Option<Buf>),
     Busy(JoinHandle<(Operation, Buf)>),
@@ -43,6 +102,7 @@ enum State {
 enum Operation {
     Read(io::Result<usize>),
     Write(io::Result<()>),
+    Seek(io::Result<u64>),
 }
 
 macro_rules! ready {
@@ -54,95 +114,534 @@ macro_rules! ready {
     };
 }
 
+impl Fh {
+    fn path(&self) -> &Path {
+        Path::new(OsStr::from_bytes(self.cpath.as_bytes()))
+    }
+
+    // Converts the error of an operation on the file to an `io::Error`, keeping its context.
+    fn io_error(
+        &self,
+        context: &crate::client::Context,
+        operation: &'static str,
+        e: crate::Error,
+    ) -> io::Error {
+        e.with_context(operation, Some(self.path()), &context.url)
+            .into()
+    }
+}
+
 impl File {
-    pub(crate) fn new(context: Arc<crate::client::Context>, file: *mut libnfs::nfsfh) -> File {
+    pub(crate) fn new(
+        context: Arc<crate::client::Context>,
+        file: *mut libnfs::nfsfh,
+        path: CString,
+        flags: OFlag,
+    ) -> File {
+        context.open_files.fetch_add(1, Ordering::Release);
+        let read_buf_size = context.max_buf_size.unwrap_or_else(|| context.read_max());
+        let write_buf_size = context.max_buf_size.unwrap_or_else(|| context.write_max());
+
         File {
+            file: Arc::new(Fh {
+                raw: file,
+                cpath: path,
+                context: Arc::clone(&context),
+                closed: std::sync::Mutex::new(None),
+            }),
+            dirty: AtomicBool::new(false),
+            append: flags.contains(OFlag::O_APPEND),
             context,
-            file: Arc::new(Fh(file)),
+            read_buf_size,
+            write_buf_size,
             inner: Mutex::new(Inner {
                 state: State::Idle(Some(Buf::with_capacity(0))),
                 last_write_err: None,
+                pos: 0,
+                write_behind: WriteBehind {
+                    depth: 0,
+                    offset: None,
+                    in_flight: VecDeque::new(),
+                },
+                read_ahead: ReadAhead {
+                    window: 0,
+                    sequential: 0,
+                    starting: false,
+                    offset: None,
+                    next: 0,
+                    in_flight: VecDeque::new(),
+                },
             }),
         }
     }
 
-    pub async fn stat(&self) -> crate::Result<crate::Stat> {
-        let context = Arc::clone(&self.context);
-        let file = Arc::clone(&self.file);
+    /// Sets the size of the buffer used for reads and writes, i.e. the largest amount of data
+    /// transferred by a single RPC.
+    ///
+    /// Defaults to the value set with
+    /// [`ClientBuilder::max_buf_size`](crate::ClientBuilder::max_buf_size), or to the transfer
+    /// sizes negotiated with the server.
+    pub fn set_max_buf_size(&mut self, size: usize) {
+        self.read_buf_size = size.max(1);
+        self.write_buf_size = size.max(1);
+    }
 
-        task::spawn_blocking(move || unsafe {
-            let mut stat = mem::MaybeUninit::uninit();
+    /// Enables write-behind with up to `depth` writes in flight. `0` disables it.
+    ///
+    /// Without write-behind, every write waits for the previous one to complete, so sequential
+    /// writes pay a full round trip per buffer. With it, writes are issued at increasing
+    /// offsets without waiting, using at most `depth` buffers of memory. Errors are reported
+    /// by a later write or by [`flush`](tokio::io::AsyncWriteExt::flush), which waits for all
+    /// writes in flight.
+    ///
+    /// Has no effect on files opened in append mode, whose writes have to land at the end of the
+    /// file one after another.
+    pub fn set_write_behind(&mut self, depth: usize) {
+        self.inner.get_mut().write_behind.depth = depth;
+    }
 
-            context.check_retcode(libnfs::nfs_fstat64(context.0, file.0, stat.as_mut_ptr()))?;
+    /// Enables read-ahead with up to `window` reads in flight. `0` disables it.
+    ///
+    /// Once a few reads in a row hit consecutive data, the following buffers are requested
+    /// ahead of the cursor, so that sequential reads aren't bound by the round-trip latency.
+    /// A seek or a write drops the reads in flight and starts over.
+    pub fn set_read_ahead(&mut self, window: usize) {
+        self.inner.get_mut().read_ahead.window = window;
+    }
 
-            Ok(stat.assume_init())
-        })
-        .await?
+    pub async fn stat(&self) -> crate::Result<crate::Metadata> {
+        self.context
+            .annotate("stat", Some(self.file.path()), async {
+                let context = Arc::clone(&self.context);
+                let file = Arc::clone(&self.file);
+
+                if let Some(driver) = &context.driver {
+                    let f = Arc::clone(&file);
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_fstat64_async(nfs, f.raw, cb, data)
+                            },
+                            move |_, stat| {
+                                drop(file);
+                                Ok(unsafe { *(stat as *const libnfs::nfs_stat_64) }.into())
+                            },
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    let mut stat = mem::MaybeUninit::uninit();
+
+                    context.check_retcode(libnfs::nfs_fstat64(
+                        context.nfs,
+                        file.raw,
+                        stat.as_mut_ptr(),
+                    ))?;
+
+                    Ok(stat.assume_init().into())
+                })
+                .await?
+            })
+            .await
+    }
+
+    /// Reads up to `buf.len()` bytes at `offset` without moving the file cursor.
+    ///
+    /// Returns the number of bytes read, which is less than `buf.len()` only at the end of the
+    /// file.
+    pub async fn read_at(&self, offset: u64, buf: &mut [u8]) -> crate::Result<usize> {
+        self.context
+            .annotate("read_at", Some(self.file.path()), async {
+                let context = Arc::clone(&self.context);
+                let file = Arc::clone(&self.file);
+                let mut data = vec![0u8; buf.len()];
+
+                let (n, data) = if let Some(driver) = &context.driver {
+                    let count = data.len() as u64;
+                    let f = Arc::clone(&file);
+
+                    driver
+                        .call(
+                            move |nfs, cb, private_data| unsafe {
+                                libnfs::nfs_pread_async(nfs, f.raw, offset, count, cb, private_data)
+                            },
+                            move |n, read| {
+                                drop(file);
+                                let n = n as usize;
+                                data[..n].copy_from_slice(unsafe {
+                                    std::slice::from_raw_parts(read as *const u8, n)
+                                });
+                                Ok((n, data))
+                            },
+                        )
+                        .await?
+                } else {
+                    task::spawn_blocking(move || unsafe {
+                        let n = context.check_retcode_ret(libnfs::nfs_pread(
+                            context.nfs,
+                            file.raw,
+                            offset,
+                            data.len() as u64,
+                            data.as_mut_ptr() as *mut c_void,
+                        ))?;
+
+                        Ok::<_, crate::Error>((n as usize, data))
+                    })
+                    .await??
+                };
+
+                buf[..n].copy_from_slice(&data[..n]);
+                Ok(n)
+            })
+            .await
+            .map_err(|e| e.with_offset(offset))
+    }
+
+    /// Writes `data` at `offset` without moving the file cursor.
+    ///
+    /// Returns the number of bytes written.
+    pub async fn write_at(&self, offset: u64, data: &[u8]) -> crate::Result<usize> {
+        self.dirty.store(true, Ordering::Relaxed);
+
+        self.context
+            .annotate("write_at", Some(self.file.path()), async {
+                let context = Arc::clone(&self.context);
+                let file = Arc::clone(&self.file);
+                let data = data.to_vec();
+
+                if let Some(driver) = &context.driver {
+                    // The buffer has to outlive the request, so hand it over to the completion.
+                    let (ptr, count) = (data.as_ptr() as usize, data.len() as u64);
+                    let f = Arc::clone(&file);
+
+                    return driver
+                        .call(
+                            move |nfs, cb, private_data| unsafe {
+                                libnfs::nfs_pwrite_async(
+                                    nfs,
+                                    f.raw,
+                                    offset,
+                                    count,
+                                    ptr as *const c_void,
+                                    cb,
+                                    private_data,
+                                )
+                            },
+                            move |n, _| {
+                                drop((file, data));
+                                Ok(n as usize)
+                            },
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context
+                        .check_retcode_ret(libnfs::nfs_pwrite(
+                            context.nfs,
+                            file.raw,
+                            offset,
+                            data.len() as u64,
+                            data.as_ptr() as *const c_void,
+                        ))
+                        .map(|n| n as usize)
+                })
+                .await?
+            })
+            .await
+            .map_err(|e| e.with_offset(offset))
+    }
+
+    pub async fn set_len(&self, size: u64) -> crate::Result<()> {
+        self.context
+            .annotate("set_len", Some(self.file.path()), async {
+                let context = Arc::clone(&self.context);
+                let file = Arc::clone(&self.file);
+
+                if let Some(driver) = &context.driver {
+                    let f = Arc::clone(&file);
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_ftruncate_async(nfs, f.raw, size, cb, data)
+                            },
+                            move |_, _| {
+                                drop(file);
+                                Ok(())
+                            },
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_ftruncate(context.nfs, file.raw, size))
+                })
+                .await?
+            })
+            .await
+    }
+
+    pub async fn set_owner(&self, uid: u32, gid: u32) -> crate::Result<()> {
+        self.context
+            .annotate("set_owner", Some(self.file.path()), async {
+                let context = Arc::clone(&self.context);
+                let file = Arc::clone(&self.file);
+
+                if let Some(driver) = &context.driver {
+                    let f = Arc::clone(&file);
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_fchown_async(
+                                    nfs, f.raw, uid as i32, gid as i32, cb, data,
+                                )
+                            },
+                            move |_, _| {
+                                drop(file);
+                                Ok(())
+                            },
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_fchown(
+                        context.nfs,
+                        file.raw,
+                        uid as i32,
+                        gid as i32,
+                    ))
+                })
+                .await?
+            })
+            .await
+    }
+
+    pub async fn set_permissions(&self, mode: Mode) -> crate::Result<()> {
+        self.context
+            .annotate("set_permissions", Some(self.file.path()), async {
+                let context = Arc::clone(&self.context);
+                let file = Arc::clone(&self.file);
+
+                if let Some(driver) = &context.driver {
+                    let f = Arc::clone(&file);
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_fchmod_async(nfs, f.raw, mode.bits() as i32, cb, data)
+                            },
+                            move |_, _| {
+                                drop(file);
+                                Ok(())
+                            },
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_fchmod(
+                        context.nfs,
+                        file.raw,
+                        mode.bits() as i32,
+                    ))
+                })
+                .await?
+            })
+            .await
+    }
+
+    /// Sets the access and modification times of the file.
+    ///
+    /// libnfs has no handle-based variant of `utimes`, so this goes through the path the file
+    /// was opened with.
+    pub async fn set_times(&self, accessed: SystemTime, modified: SystemTime) -> crate::Result<()> {
+        self.context
+            .annotate("set_times", Some(self.file.path()), async {
+                let context = Arc::clone(&self.context);
+                let file = Arc::clone(&self.file);
+                let mut times = [
+                    crate::client::timeval(accessed)?,
+                    crate::client::timeval(modified)?,
+                ];
+
+                if let Some(driver) = &context.driver {
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_utimes_async(
+                                    nfs,
+                                    file.cpath.as_ptr(),
+                                    times.as_mut_ptr(),
+                                    cb,
+                                    data,
+                                )
+                            },
+                            |_, _| Ok(()),
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_utimes(
+                        context.nfs,
+                        file.cpath.as_ptr(),
+                        times.as_mut_ptr(),
+                    ))
+                })
+                .await?
+            })
+            .await
     }
 
     pub async fn sync_all(&self) -> crate::Result<()> {
+        self.context
+            .annotate("sync_all", Some(self.file.path()), async {
+                let context = Arc::clone(&self.context);
+                let file = Arc::clone(&self.file);
+
+                if let Some(driver) = &context.driver {
+                    let f = Arc::clone(&file);
+                    return driver
+                        .call(
+                            move |nfs, cb, data| unsafe {
+                                libnfs::nfs_fsync_async(nfs, f.raw, cb, data)
+                            },
+                            move |_, _| {
+                                drop(file);
+                                Ok(())
+                            },
+                        )
+                        .await;
+                }
+
+                task::spawn_blocking(move || unsafe {
+                    context.check_retcode(libnfs::nfs_fsync(context.nfs, file.raw))
+                })
+                .await?
+            })
+            .await
+    }
+
+    /// Flushes pending writes, commits them to stable storage and closes the file.
+    ///
+    /// Dropping a file closes it in the background and ignores any errors, while servers may
+    /// report failed writes only when they are committed. Use `close` to find out about them.
+    pub async fn close(mut self) -> crate::Result<()> {
+        poll_fn(|cx| Pin::new(&mut self).poll_flush(cx))
+            .await
+            .map_err(crate::error::from_io)?;
+
+        if *self.dirty.get_mut() {
+            self.sync_all().await?;
+        }
+
+        // Reads ahead, even cancelled ones, may still be in flight, so leave closing the handle
+        // to whichever of them completes last
+        let (tx, rx) = oneshot::channel();
+        *self
+            .file
+            .closed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(tx);
         let context = Arc::clone(&self.context);
-        let file = Arc::clone(&self.file);
+        let path = self.file.path().to_path_buf();
+        drop(self);
 
-        task::spawn_blocking(move || unsafe {
-            context.check_retcode(libnfs::nfs_fsync(context.0, file.0))
-        })
-        .await?
+        context
+            .annotate("close", Some(&path), async {
+                rx.await.unwrap_or_else(|_| {
+                    Err(crate::error::nfs(
+                        "file handle was dropped without closing it",
+                        io::ErrorKind::Other,
+                    ))
+                })
+            })
+            .await
     }
 }
 
-// AsyncRead and AsyncWrite implementation is shamelessly stolen from Tokio.
-impl AsyncRead for File {
-    fn poll_read(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        dst: &mut ReadBuf<'_>,
-    ) -> Poll<std::io::Result<()>> {
-        let me = self.get_mut();
-        let inner = me.inner.get_mut();
+// AsyncRead, AsyncWrite and AsyncSeek implementation is shamelessly stolen from Tokio.
+impl File {
+    // Makes the buffer hold the data at the cursor, reading up to `want` bytes if it's empty.
+    // The buffer is left empty at the end of file.
+    fn poll_fill(&mut self, cx: &mut Context<'_>, want: usize) -> Poll<io::Result<()>> {
+        let inner = self.inner.get_mut();
+
+        if inner.write_behind.is_active() {
+            ready!(inner.poll_settle(cx, &self.context, &self.file))?;
+        }
 
         loop {
+            let inner = self.inner.get_mut();
             match inner.state {
                 State::Idle(ref mut buf_cell) => {
                     let mut buf = buf_cell.take().unwrap();
 
                     if !buf.is_empty() {
-                        buf.copy_to(dst);
                         *buf_cell = Some(buf);
                         return Poll::Ready(Ok(()));
                     }
 
-                    buf.ensure_capacity_for(dst);
+                    let ra = &mut inner.read_ahead;
+                    if ra.offset.is_some() {
+                        // Reading ahead stops at the end of file by moving the cursor back, so
+                        // wait for that before reporting it
+                        *buf_cell = Some(buf);
+                        ready!(self.poll_read_ahead(cx))?;
+                        continue;
+                    }
+                    if ra.window > 0 && ra.sequential >= SEQUENTIAL_READS && !ra.starting {
+                        // Find out where the cursor is to read ahead from there
+                        ra.starting = true;
+                        inner.state = State::Busy(spawn_seek(
+                            &self.context,
+                            &self.file,
+                            buf,
+                            0,
+                            Whence::SeekCur,
+                        ));
+                        continue;
+                    }
 
-                    let context = Arc::clone(&me.context);
-                    let file = Arc::clone(&me.file);
+                    buf.ensure_capacity(want.min(self.read_buf_size));
 
-                    inner.state = State::Busy(task::spawn_blocking(move || unsafe {
-                        let res = context
-                            .check_retcode_ret(libnfs::nfs_read(
-                                context.0,
-                                file.0,
-                                buf.len() as u64,
-                                buf.mut_bytes().as_mut_ptr() as *mut c_void,
-                            ))
-                            .map(|r| r as usize)
-                            .map_err(|e| e.into_io());
-
-                        if let Ok(n) = res {
-                            buf.truncate(n);
-                        } else {
-                            buf.clear();
-                        }
+                    let context = Arc::clone(&self.context);
+                    let file = Arc::clone(&self.file);
+
+                    inner.state = State::Busy(if context.driver.is_some() {
+                        spawn_op(context.hold(), read_async(context, file, buf))
+                    } else {
+                        spawn_blocking_op(context.hold(), move || unsafe {
+                            let offset = libnfs::nfs_get_current_offset(file.raw);
+                            let res = context
+                                .check_retcode_ret(libnfs::nfs_read(
+                                    context.nfs,
+                                    file.raw,
+                                    buf.len() as u64,
+                                    buf.mut_bytes().as_mut_ptr() as *mut c_void,
+                                ))
+                                .map(|r| r as usize)
+                                .map_err(|e| {
+                                    file.io_error(&context, "read", e.with_offset(offset))
+                                });
+
+                            if let Ok(n) = res {
+                                buf.truncate(n);
+                            } else {
+                                buf.clear();
+                            }
 
-                        (Operation::Read(res), buf)
-                    }))
+                            (Operation::Read(res), buf)
+                        })
+                    })
                 }
                 State::Busy(ref mut rx) => {
-                    let (op, mut buf) = ready!(Pin::new(rx).poll(cx))?;
+                    let (op, buf) = ready!(Pin::new(rx).poll(cx))?;
 
                     match op {
                         Operation::Read(Ok(_)) => {
-                            buf.copy_to(dst);
+                            inner.read_ahead.sequential += 1;
                             inner.state = State::Idle(Some(buf));
                             return Poll::Ready(Ok(()));
                         }
@@ -158,9 +657,24 @@ impl AsyncRead for File {
                             continue;
                         }
                         Operation::Write(Err(e)) => {
-                            assert!(inner.last_write_err.is_none());
-                            inner.last_write_err = Some(e.kind());
                             inner.state = State::Idle(Some(buf));
+                            if let Err(e) = inner.defer_write_err(e) {
+                                return Poll::Ready(Err(e));
+                            }
+                        }
+                        Operation::Seek(res) => {
+                            assert!(buf.is_empty());
+                            if let Ok(pos) = res {
+                                inner.pos = pos;
+
+                                let ra = &mut inner.read_ahead;
+                                if mem::take(&mut ra.starting) {
+                                    ra.offset = Some(pos);
+                                    ra.next = pos;
+                                }
+                            }
+                            inner.state = State::Idle(Some(buf));
+                            continue;
                         }
                     }
                 }
@@ -169,6 +683,33 @@ impl AsyncRead for File {
     }
 }
 
+impl AsyncRead for File {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        dst: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let me = self.get_mut();
+        ready!(me.poll_fill(cx, dst.remaining()))?;
+
+        me.inner.get_mut().buf().copy_to(dst);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncBufRead for File {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let me = self.get_mut();
+        ready!(me.poll_fill(cx, me.read_buf_size))?;
+
+        Poll::Ready(Ok(me.inner.get_mut().buf().bytes()))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().inner.get_mut().buf().consume(amt);
+    }
+}
+
 impl AsyncWrite for File {
     fn poll_write(
         self: Pin<&mut Self>,
@@ -178,8 +719,23 @@ impl AsyncWrite for File {
         let me = self.get_mut();
         let inner = me.inner.get_mut();
 
+        // Writes behind have to settle before the write-through path takes over, and any of them
+        // failing has to be reported before issuing new writes
+        if inner.write_behind.depth == 0 && inner.write_behind.is_active() {
+            ready!(inner.poll_settle(cx, &me.context, &me.file))?;
+        }
         if let Some(e) = inner.last_write_err.take() {
-            return Poll::Ready(Err(e.into()));
+            return Poll::Ready(Err(e));
+        }
+        me.dirty.store(true, Ordering::Relaxed);
+
+        if inner.read_ahead.offset.is_some() {
+            inner.stop_read_ahead(&me.context, &me.file);
+        }
+        inner.read_ahead.reset();
+
+        if inner.write_behind.depth > 0 && !me.append {
+            return me.poll_write_behind(cx, src);
         }
 
         loop {
@@ -193,51 +749,60 @@ impl AsyncWrite for File {
                         None
                     };
 
-                    let n = buf.copy_from(src);
+                    let n = buf.copy_from(src, me.write_buf_size);
                     let context = Arc::clone(&me.context);
                     let file = Arc::clone(&me.file);
 
-                    inner.state = State::Busy(task::spawn_blocking(move || unsafe {
-                        let mut cur_offset: u64 = 0;
+                    inner.state = State::Busy(if context.driver.is_some() {
+                        spawn_op(context.hold(), write_async(context, file, buf, seek))
+                    } else {
+                        spawn_blocking_op(context.hold(), move || unsafe {
+                            let mut cur_offset = libnfs::nfs_get_current_offset(file.raw);
 
-                        if let Some(seek) = seek {
-                            let res = context
-                                .check_retcode(libnfs::nfs_lseek(
-                                    context.0,
-                                    file.0,
-                                    seek,
-                                    Whence::SeekCur as i32,
-                                    &mut cur_offset as *mut u64,
-                                ))
-                                .map_err(|e| e.into_io());
+                            if let Some(seek) = seek {
+                                let res = context
+                                    .check_retcode(libnfs::nfs_lseek(
+                                        context.nfs,
+                                        file.raw,
+                                        seek,
+                                        Whence::SeekCur as i32,
+                                        &mut cur_offset as *mut u64,
+                                    ))
+                                    .map_err(|e| {
+                                        let offset = cur_offset.saturating_add_signed(seek);
+                                        file.io_error(&context, "write", e.with_offset(offset))
+                                    });
 
-                            if res.is_err() {
-                                return (Operation::Write(res), buf);
+                                if res.is_err() {
+                                    return (Operation::Write(res), buf);
+                                }
                             }
-                        }
 
-                        let mut written: usize = 0;
-                        while written < buf.len() {
-                            match context
-                                .check_retcode_ret(libnfs::nfs_write(
-                                    context.0,
-                                    file.0,
-                                    (buf.len() - written) as u64,
-                                    buf.mut_bytes()[written..].as_mut_ptr() as *mut c_void,
-                                ))
-                                .map_err(|e| e.into_io())
-                            {
-                                Ok(n) => written += n as usize,
-                                Err(e) => {
-                                    buf.clear();
-                                    return (Operation::Write(Err(e)), buf);
-                                }
-                            };
-                        }
+                            let mut written: usize = 0;
+                            while written < buf.len() {
+                                match context
+                                    .check_retcode_ret(libnfs::nfs_write(
+                                        context.nfs,
+                                        file.raw,
+                                        (buf.len() - written) as u64,
+                                        buf.mut_bytes()[written..].as_mut_ptr() as *mut c_void,
+                                    ))
+                                    .map_err(|e| {
+                                        let offset = cur_offset + written as u64;
+                                        file.io_error(&context, "write", e.with_offset(offset))
+                                    }) {
+                                    Ok(n) => written += n as usize,
+                                    Err(e) => {
+                                        buf.clear();
+                                        return (Operation::Write(Err(e)), buf);
+                                    }
+                                };
+                            }
 
-                        buf.clear();
-                        (Operation::Write(Ok(())), buf)
-                    }));
+                            buf.clear();
+                            (Operation::Write(Ok(())), buf)
+                        })
+                    });
 
                     return Poll::Ready(Ok(n));
                 }
@@ -253,14 +818,23 @@ impl AsyncWrite for File {
                             res?;
                             continue;
                         }
+                        Operation::Seek(_) => {
+                            // Ignore the seek
+                            continue;
+                        }
                     }
                 }
             }
         }
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        let inner = self.inner.get_mut();
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        let me = self.get_mut();
+        let inner = me.inner.get_mut();
+
+        if inner.write_behind.is_active() {
+            ready!(inner.poll_settle(cx, &me.context, &me.file))?;
+        }
         inner.poll_flush(cx)
     }
 
@@ -269,10 +843,308 @@ impl AsyncWrite for File {
     }
 }
 
+impl AsyncSeek for File {
+    fn start_seek(self: Pin<&mut Self>, mut pos: SeekFrom) -> io::Result<()> {
+        let me = self.get_mut();
+        let inner = me.inner.get_mut();
+
+        if inner.write_behind.is_active() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "writes are in flight, call poll_complete before start_seek",
+            ));
+        }
+
+        match inner.state {
+            State::Busy(_) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "other file operation is pending, call poll_complete before start_seek",
+            )),
+            State::Idle(ref mut buf_cell) => {
+                let mut buf = buf_cell.take().unwrap();
+
+                // The libnfs cursor lags behind while reading ahead, so make the seek absolute
+                let ra = &mut inner.read_ahead;
+                if let Some(offset) = ra.offset {
+                    if let SeekFrom::Current(n) = pos {
+                        match (offset - buf.len() as u64).checked_add_signed(n) {
+                            Some(target) => pos = SeekFrom::Start(target),
+                            None => {
+                                *buf_cell = Some(buf);
+                                return Err(io::Error::new(
+                                    io::ErrorKind::InvalidInput,
+                                    "invalid seek to a negative or overflowing position",
+                                ));
+                            }
+                        }
+                    }
+
+                    ra.offset = None;
+                    ra.cancel();
+                    buf.clear();
+                }
+                ra.reset();
+
+                // Factor in any unread data from the buf
+                if !buf.is_empty() {
+                    let n = buf.discard_read();
+
+                    if let SeekFrom::Current(ref mut offset) = pos {
+                        *offset += n;
+                    }
+                }
+
+                let (offset, whence) = match pos {
+                    SeekFrom::Start(offset) => (offset as i64, Whence::SeekSet),
+                    SeekFrom::Current(offset) => (offset, Whence::SeekCur),
+                    SeekFrom::End(offset) => (offset, Whence::SeekEnd),
+                };
+                inner.state = State::Busy(spawn_seek(&me.context, &me.file, buf, offset, whence));
+                Ok(())
+            }
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let me = self.get_mut();
+        let inner = me.inner.get_mut();
+
+        loop {
+            match inner.state {
+                State::Idle(_) if inner.write_behind.is_active() => {
+                    ready!(inner.poll_settle(cx, &me.context, &me.file))?;
+                }
+                State::Idle(_) => return Poll::Ready(Ok(inner.pos)),
+                State::Busy(ref mut rx) => {
+                    let (op, buf) = ready!(Pin::new(rx).poll(cx))?;
+                    inner.state = State::Idle(Some(buf));
+
+                    match op {
+                        Operation::Read(_) => {}
+                        Operation::Write(Err(e)) => inner.defer_write_err(e)?,
+                        Operation::Write(_) => {}
+                        Operation::Seek(res) => {
+                            if let Ok(pos) = res {
+                                inner.pos = pos;
+                            }
+                            return Poll::Ready(res);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl File {
+    fn poll_write_behind(&mut self, cx: &mut Context<'_>, src: &[u8]) -> Poll<io::Result<usize>> {
+        let inner = self.inner.get_mut();
+
+        loop {
+            match inner.state {
+                State::Idle(ref mut buf_cell) => {
+                    let wb = &mut inner.write_behind;
+
+                    let Some(offset) = wb.offset else {
+                        // Find out where the cursor is, minus whatever was read into the buffer
+                        // but not consumed yet
+                        let mut buf = buf_cell.take().unwrap();
+                        let seek = if !buf.is_empty() {
+                            buf.discard_read()
+                        } else {
+                            0
+                        };
+
+                        inner.state = State::Busy(spawn_seek(
+                            &self.context,
+                            &self.file,
+                            buf,
+                            seek,
+                            Whence::SeekCur,
+                        ));
+                        continue;
+                    };
+
+                    // Bound memory usage by waiting for the oldest write
+                    while wb.in_flight.len() >= wb.depth {
+                        let res = ready!(Pin::new(wb.in_flight.front_mut().unwrap()).poll(cx))?;
+                        wb.in_flight.pop_front();
+                        res?;
+                    }
+
+                    let n = src.len().min(self.write_buf_size);
+                    wb.in_flight.push_back(spawn_pwrite(
+                        &self.context,
+                        &self.file,
+                        offset,
+                        src[..n].to_vec(),
+                    ));
+                    wb.offset = Some(offset + n as u64);
+
+                    return Poll::Ready(Ok(n));
+                }
+                State::Busy(ref mut rx) => {
+                    let (op, buf) = ready!(Pin::new(rx).poll(cx))?;
+                    inner.state = State::Idle(Some(buf));
+
+                    match op {
+                        Operation::Read(_) => {}
+                        Operation::Write(res) => res?,
+                        Operation::Seek(res) => {
+                            let pos = res?;
+                            inner.pos = pos;
+                            inner.write_behind.offset = Some(pos);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl File {
+    fn poll_read_ahead(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let inner = self.inner.get_mut();
+        let ra = &mut inner.read_ahead;
+        let offset = ra.offset.unwrap();
+
+        while ra.in_flight.len() < ra.window.max(1) {
+            let len = self.read_buf_size;
+            ra.in_flight
+                .push_back((len, spawn_pread(&self.context, &self.file, ra.next, len)));
+            ra.next += len as u64;
+        }
+
+        let (len, read) = ra.in_flight.front_mut().unwrap();
+        let len = *len;
+        let res = ready!(Pin::new(read).poll(cx));
+        ra.in_flight.pop_front();
+
+        let data = match res.map_err(io::Error::from).and_then(|res| res) {
+            Ok(data) => data,
+            Err(e) => {
+                inner.stop_read_ahead(&self.context, &self.file);
+                return Poll::Ready(Err(e));
+            }
+        };
+
+        if data.len() < len {
+            // The end of the file or a server returning less than asked, either way the reads
+            // after this one are at the wrong offsets
+            ra.cancel();
+            ra.next = offset + data.len() as u64;
+        }
+        if data.is_empty() {
+            inner.stop_read_ahead(&self.context, &self.file);
+            return Poll::Ready(Ok(()));
+        }
+
+        ra.offset = Some(offset + data.len() as u64);
+        inner.buf().replace(data);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl ReadAhead {
+    // Drops the reads in flight. Their requests can't be recalled, so they complete in the
+    // background.
+    fn cancel(&mut self) {
+        self.in_flight.clear();
+    }
+
+    fn reset(&mut self) {
+        self.sequential = 0;
+        self.starting = false;
+    }
+}
+
+impl WriteBehind {
+    fn is_active(&self) -> bool {
+        self.offset.is_some() || !self.in_flight.is_empty()
+    }
+}
+
 impl Inner {
+    // Returns the buffer of an idle file.
+    fn buf(&mut self) -> &mut Buf {
+        match self.state {
+            State::Idle(Some(ref mut buf)) => buf,
+            _ => unreachable!("file operation pending"),
+        }
+    }
+
+    // Drops the reads in flight and moves the file cursor to the data not consumed yet.
+    fn stop_read_ahead(&mut self, context: &Arc<crate::client::Context>, file: &Arc<Fh>) {
+        let Some(offset) = self.read_ahead.offset.take() else {
+            return;
+        };
+        self.read_ahead.cancel();
+        self.read_ahead.reset();
+
+        let mut buf = match self.state {
+            State::Idle(ref mut buf_cell) => buf_cell.take().unwrap(),
+            State::Busy(_) => unreachable!("file operation pending during read-ahead"),
+        };
+        let pos = offset - buf.len() as u64;
+        buf.clear();
+
+        self.state = State::Busy(spawn_seek(context, file, buf, pos as i64, Whence::SeekSet));
+    }
+
+    // Waits for the writes in flight and moves the file cursor past them.
+    //
+    // Write errors are recorded in `last_write_err`, so that they are reported by the next
+    // write or flush.
+    fn poll_settle(
+        &mut self,
+        cx: &mut Context<'_>,
+        context: &Arc<crate::client::Context>,
+        file: &Arc<Fh>,
+    ) -> Poll<io::Result<()>> {
+        while let Some(write) = self.write_behind.in_flight.front_mut() {
+            let res = ready!(Pin::new(write).poll(cx))?;
+            self.write_behind.in_flight.pop_front();
+
+            if let Err(e) = res {
+                self.last_write_err.get_or_insert(e);
+            }
+        }
+
+        if let Some(offset) = self.write_behind.offset.take() {
+            let buf = match self.state {
+                State::Idle(ref mut buf_cell) => buf_cell.take().unwrap(),
+                State::Busy(_) => unreachable!("file operation pending during write-behind"),
+            };
+
+            // The seek is completed by whichever operation polls the file next
+            self.state = State::Busy(spawn_seek(
+                context,
+                file,
+                buf,
+                offset as i64,
+                Whence::SeekSet,
+            ));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    // Records a write error to be reported by the next write or flush. If one is pending already,
+    // the new one is returned to be reported right away instead.
+    fn defer_write_err(&mut self, e: io::Error) -> io::Result<()> {
+        match self.last_write_err {
+            Some(_) => Err(e),
+            None => {
+                self.last_write_err = Some(e);
+                Ok(())
+            }
+        }
+    }
+
     fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
         if let Some(e) = self.last_write_err.take() {
-            return Poll::Ready(Err(e.into()));
+            return Poll::Ready(Err(e));
         }
 
         let (op, buf) = match self.state {
@@ -286,17 +1158,383 @@ impl Inner {
         match op {
             Operation::Read(_) => Poll::Ready(Ok(())),
             Operation::Write(res) => Poll::Ready(res),
+            Operation::Seek(_) => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+// Spawns an operation on the file, kept in flight until it completes so that `umount` waits for
+// it even if the file is dropped in the meantime.
+fn spawn_op<T, F>(in_flight: InFlight<Arc<crate::client::Context>>, f: F) -> JoinHandle<T>
+where
+    T: Send + 'static,
+    F: Future<Output = T> + Send + 'static,
+{
+    task::spawn(async move {
+        let _in_flight = in_flight;
+        f.await
+    })
+}
+
+// Same as `spawn_op`, for the blocking libnfs calls.
+fn spawn_blocking_op<T, F>(in_flight: InFlight<Arc<crate::client::Context>>, f: F) -> JoinHandle<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    task::spawn_blocking(move || {
+        let _in_flight = in_flight;
+        f()
+    })
+}
+
+fn spawn_seek(
+    context: &Arc<crate::client::Context>,
+    file: &Arc<Fh>,
+    buf: Buf,
+    offset: i64,
+    whence: Whence,
+) -> JoinHandle<(Operation, Buf)> {
+    let context = Arc::clone(context);
+    let file = Arc::clone(file);
+
+    if context.driver.is_some() {
+        spawn_op(
+            context.hold(),
+            seek_async(context, file, buf, offset, whence),
+        )
+    } else {
+        spawn_blocking_op(context.hold(), move || unsafe {
+            let mut cur_offset: u64 = 0;
+
+            let res = context
+                .check_retcode(libnfs::nfs_lseek(
+                    context.nfs,
+                    file.raw,
+                    offset,
+                    whence as i32,
+                    &mut cur_offset as *mut u64,
+                ))
+                .map(|_| cur_offset)
+                .map_err(|e| file.io_error(&context, "seek", e));
+
+            (Operation::Seek(res), buf)
+        })
+    }
+}
+
+fn spawn_pread(
+    context: &Arc<crate::client::Context>,
+    file: &Arc<Fh>,
+    offset: u64,
+    len: usize,
+) -> JoinHandle<io::Result<Vec<u8>>> {
+    let context = Arc::clone(context);
+    let file = Arc::clone(file);
+
+    if context.driver.is_some() {
+        spawn_op(context.hold(), pread_async(context, file, offset, len))
+    } else {
+        spawn_blocking_op(context.hold(), move || unsafe {
+            let mut data = vec![0u8; len];
+            let n = context
+                .check_retcode_ret(libnfs::nfs_pread(
+                    context.nfs,
+                    file.raw,
+                    offset,
+                    len as u64,
+                    data.as_mut_ptr() as *mut c_void,
+                ))
+                .map_err(|e| file.io_error(&context, "read", e.with_offset(offset)))?;
+
+            data.truncate(n as usize);
+            Ok(data)
+        })
+    }
+}
+
+fn spawn_pwrite(
+    context: &Arc<crate::client::Context>,
+    file: &Arc<Fh>,
+    offset: u64,
+    data: Vec<u8>,
+) -> JoinHandle<io::Result<()>> {
+    let context = Arc::clone(context);
+    let file = Arc::clone(file);
+
+    if context.driver.is_some() {
+        spawn_op(context.hold(), pwrite_async(context, file, offset, data))
+    } else {
+        spawn_blocking_op(context.hold(), move || unsafe {
+            let mut written: usize = 0;
+            while written < data.len() {
+                let n = context
+                    .check_retcode_ret(libnfs::nfs_pwrite(
+                        context.nfs,
+                        file.raw,
+                        offset + written as u64,
+                        (data.len() - written) as u64,
+                        data[written..].as_ptr() as *const c_void,
+                    ))
+                    .map_err(|e| {
+                        file.io_error(&context, "write", e.with_offset(offset + written as u64))
+                    })?;
+                if n == 0 {
+                    return Err(io::ErrorKind::WriteZero.into());
+                }
+                written += n as usize;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+async fn read_async(
+    context: Arc<crate::client::Context>,
+    file: Arc<Fh>,
+    mut buf: Buf,
+) -> (Operation, Buf) {
+    let driver = context.driver.as_ref().unwrap();
+    let count = buf.len() as u64;
+    let offset = unsafe { libnfs::nfs_get_current_offset(file.raw) };
+    let (f, fh) = (Arc::clone(&file), Arc::clone(&file));
+
+    let res = driver
+        .call(
+            move |nfs, cb, data| unsafe { libnfs::nfs_read_async(nfs, f.raw, count, cb, data) },
+            move |n, data| {
+                drop(fh);
+                let n = n as usize;
+                buf.mut_bytes()[..n]
+                    .copy_from_slice(unsafe { std::slice::from_raw_parts(data as *const u8, n) });
+                buf.truncate(n);
+                Ok((n, buf))
+            },
+        )
+        .await;
+
+    match res {
+        Ok((n, buf)) => (Operation::Read(Ok(n)), buf),
+        Err(e) => (
+            Operation::Read(Err(file.io_error(&context, "read", e.with_offset(offset)))),
+            Buf::with_capacity(0),
+        ),
+    }
+}
+
+async fn write_async(
+    context: Arc<crate::client::Context>,
+    file: Arc<Fh>,
+    mut buf: Buf,
+    seek: Option<i64>,
+) -> (Operation, Buf) {
+    let driver = context.driver.as_ref().unwrap();
+    let mut offset = unsafe { libnfs::nfs_get_current_offset(file.raw) };
+
+    if let Some(seek) = seek {
+        let (f, fh) = (Arc::clone(&file), Arc::clone(&file));
+        let res = driver
+            .call(
+                move |nfs, cb, data| unsafe {
+                    libnfs::nfs_lseek_async(nfs, f.raw, seek, Whence::SeekCur as i32, cb, data)
+                },
+                move |_, offset| {
+                    drop(fh);
+                    Ok(unsafe { *(offset as *const u64) })
+                },
+            )
+            .await;
+
+        match res {
+            Ok(pos) => offset = pos,
+            Err(e) => {
+                let e = e.with_offset(offset.saturating_add_signed(seek));
+                return (
+                    Operation::Write(Err(file.io_error(&context, "write", e))),
+                    buf,
+                );
+            }
+        }
+    }
+
+    let mut written: usize = 0;
+    while written < buf.len() {
+        // The buffer has to outlive the request, so hand it over to the completion.
+        let ptr = buf.bytes()[written..].as_ptr() as usize;
+        let count = (buf.len() - written) as u64;
+        let (f, fh) = (Arc::clone(&file), Arc::clone(&file));
+
+        match driver
+            .call(
+                move |nfs, cb, data| unsafe {
+                    libnfs::nfs_write_async(nfs, f.raw, count, ptr as *const c_void, cb, data)
+                },
+                move |n, _| {
+                    drop(fh);
+                    Ok((n as usize, buf))
+                },
+            )
+            .await
+        {
+            Ok((n, b)) => {
+                written += n;
+                buf = b;
+            }
+            Err(e) => {
+                let e = e.with_offset(offset + written as u64);
+                return (
+                    Operation::Write(Err(file.io_error(&context, "write", e))),
+                    Buf::with_capacity(0),
+                );
+            }
+        }
+    }
+
+    buf.clear();
+    (Operation::Write(Ok(())), buf)
+}
+
+async fn pread_async(
+    context: Arc<crate::client::Context>,
+    file: Arc<Fh>,
+    offset: u64,
+    len: usize,
+) -> io::Result<Vec<u8>> {
+    let driver = context.driver.as_ref().unwrap();
+    let (f, fh) = (Arc::clone(&file), Arc::clone(&file));
+
+    driver
+        .call(
+            move |nfs, cb, data| unsafe {
+                libnfs::nfs_pread_async(nfs, f.raw, offset, len as u64, cb, data)
+            },
+            move |n, data| {
+                drop(fh);
+                Ok(unsafe { std::slice::from_raw_parts(data as *const u8, n as usize) }.to_vec())
+            },
+        )
+        .await
+        .map_err(|e| file.io_error(&context, "read", e.with_offset(offset)))
+}
+
+async fn pwrite_async(
+    context: Arc<crate::client::Context>,
+    file: Arc<Fh>,
+    offset: u64,
+    mut data: Vec<u8>,
+) -> io::Result<()> {
+    let driver = context.driver.as_ref().unwrap();
+
+    let mut written: usize = 0;
+    while written < data.len() {
+        // The buffer has to outlive the request, so hand it over to the completion.
+        let ptr = data[written..].as_ptr() as usize;
+        let count = (data.len() - written) as u64;
+        let at = offset + written as u64;
+        let (f, fh) = (Arc::clone(&file), Arc::clone(&file));
+
+        let (n, d) = driver
+            .call(
+                move |nfs, cb, private_data| unsafe {
+                    libnfs::nfs_pwrite_async(
+                        nfs,
+                        f.raw,
+                        at,
+                        count,
+                        ptr as *const c_void,
+                        cb,
+                        private_data,
+                    )
+                },
+                move |n, _| {
+                    drop(fh);
+                    Ok((n as usize, data))
+                },
+            )
+            .await
+            .map_err(|e| file.io_error(&context, "write", e.with_offset(at)))?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
         }
+
+        written += n;
+        data = d;
     }
+
+    Ok(())
 }
 
-impl Drop for File {
+async fn seek_async(
+    context: Arc<crate::client::Context>,
+    file: Arc<Fh>,
+    buf: Buf,
+    offset: i64,
+    whence: Whence,
+) -> (Operation, Buf) {
+    let driver = context.driver.as_ref().unwrap();
+    let (f, fh) = (Arc::clone(&file), Arc::clone(&file));
+
+    let res = driver
+        .call(
+            move |nfs, cb, data| unsafe {
+                libnfs::nfs_lseek_async(nfs, f.raw, offset, whence as i32, cb, data)
+            },
+            move |_, offset| {
+                drop(fh);
+                Ok(unsafe { *(offset as *const u64) })
+            },
+        )
+        .await
+        .map_err(|e| file.io_error(&context, "seek", e));
+
+    (Operation::Seek(res), buf)
+}
+
+impl Drop for Fh {
     fn drop(&mut self) {
-        if !self.context.0.is_null() && !self.file.0.is_null() {
-            let context = self.context.clone();
-            let file = self.file.clone();
+        let raw = self.raw as usize;
+        let closed = self
+            .closed
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let in_flight = self.context.hold();
+        let report = move |res: crate::Result<()>| {
+            drop(in_flight);
+            if let Some(closed) = closed {
+                let _ = closed.send(res);
+            }
+        };
+
+        match self.context.driver {
+            Some(ref driver) => {
+                let _ = driver.spawn(
+                    move |nfs, cb, data| unsafe {
+                        libnfs::nfs_close_async(nfs, raw as *mut libnfs::nfsfh, cb, data)
+                    },
+                    move |res| report(res.map(|_| ())),
+                );
+            }
+            None => {
+                let context = Arc::clone(&self.context);
+                let close =
+                    move || unsafe {
+                        report(context.check_retcode(libnfs::nfs_close(
+                            context.nfs,
+                            raw as *mut libnfs::nfsfh,
+                        )));
+                    };
 
-            unsafe { libnfs::nfs_close(context.0, file.0) };
+                // Closing may commit unstable writes, don't stall the executor for a round trip
+                match runtime::Handle::try_current() {
+                    Ok(handle) => drop(handle.spawn_blocking(close)),
+                    Err(_) => close(),
+                }
+            }
         }
+
+        // Only now that nothing uses the handle anymore may the export be unmounted
+        self.context.open_files.fetch_sub(1, Ordering::Release);
     }
 }
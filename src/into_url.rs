@@ -30,6 +30,7 @@ impl IntoUrlSealed for Url {
         if self.fragment().is_some() {
             urle("fragment is present")?;
         }
+        crate::MountOptions::from_url(&self)?;
 
         Ok(self)
     }
@@ -59,12 +60,12 @@ mod tests {
 
     #[test]
     fn valid_url() {
-        let url = "nfs://127.0.0.1:1244/share?opt1=val&opt2=val2"
+        let url = "nfs://127.0.0.1:1244/share?uid=1000&version=3"
             .into_url()
             .unwrap();
         assert_eq!(
             url.to_string(),
-            "nfs://127.0.0.1:1244/share?opt1=val&opt2=val2"
+            "nfs://127.0.0.1:1244/share?uid=1000&version=3"
         );
     }
 
@@ -95,6 +96,12 @@ mod tests {
         assert_eq!(err.to_string(), "URL error: fragment is present")
     }
 
+    #[test]
+    fn unknown_option() {
+        let err = "nfs://127.0.0.1/share?opt1=val".into_url().unwrap_err();
+        assert_eq!(err.to_string(), "URL error: unknown mount option `opt1`")
+    }
+
     #[test]
     fn invalid_url() {
         let _ = "nfs/127.0.1share".into_url().unwrap_err();
@@ -1,17 +1,29 @@
+pub mod blocking;
+
 mod buf;
 mod client;
+mod dir;
+mod driver;
 mod error;
 mod file;
 mod into_url;
+mod metadata;
+mod open_options;
+mod options;
+mod walk;
 
 use std::ffi::{CStr, CString};
 use std::os::unix::ffi::OsStrExt;
 
-pub use self::client::Client;
-pub use self::error::{Error, Result};
+pub use self::client::{Backend, Client, ClientBuilder};
+pub use self::dir::{DirEntry, ReadDir};
+pub use self::error::{Error, ErrorKind, NfsStatus, Result};
 pub use self::file::File;
 pub use self::into_url::IntoUrl;
-pub use libnfs_sys::nfs_stat_64 as Stat;
+pub use self::metadata::{FileType, FsStats, Metadata};
+pub use self::open_options::OpenOptions;
+pub use self::options::{MountOptions, Version};
+pub use self::walk::{Walk, WalkEntry};
 
 trait ToStringLossy {
     fn to_string_lossy(&self) -> String;
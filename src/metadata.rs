@@ -0,0 +1,334 @@
+use libnfs_sys as libnfs;
+use nix::sys::stat::{Mode, SFlag};
+use std::{
+    io,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Metadata information about a file.
+///
+/// Returned by [`Client::stat`](crate::Client::stat), [`Client::lstat`](crate::Client::lstat),
+/// [`File::stat`](crate::File::stat) and [`DirEntry::metadata`](crate::DirEntry::metadata).
+#[derive(Clone, Copy)]
+pub struct Metadata(libnfs::nfs_stat_64);
+
+/// Filesystem statistics.
+///
+/// Returned by [`Client::statvfs`](crate::Client::statvfs). Block counts are in units of
+/// [`fragment_size`](FsStats::fragment_size).
+#[derive(Clone, Copy)]
+pub struct FsStats(libnfs::nfs_statvfs_64);
+
+/// The type of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileType {
+    File,
+    Dir,
+    Symlink,
+    BlockDevice,
+    CharDevice,
+    Socket,
+    Fifo,
+    Unknown,
+}
+
+impl Metadata {
+    /// Returns the type of the file.
+    pub fn file_type(&self) -> FileType {
+        FileType::from_mode(self.0.nfs_mode)
+    }
+
+    /// Returns `true` if the file is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.file_type().is_dir()
+    }
+
+    /// Returns `true` if the file is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.file_type().is_file()
+    }
+
+    /// Returns `true` if the file is a symbolic link.
+    pub fn is_symlink(&self) -> bool {
+        self.file_type().is_symlink()
+    }
+
+    /// Returns the size of the file in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.0.nfs_size
+    }
+
+    /// Returns the permission bits of the file.
+    pub fn permissions(&self) -> Mode {
+        Mode::from_bits_truncate(self.0.nfs_mode as _)
+    }
+
+    /// Returns the user ID of the owner of the file.
+    pub fn uid(&self) -> u32 {
+        self.0.nfs_uid as u32
+    }
+
+    /// Returns the group ID of the owner of the file.
+    pub fn gid(&self) -> u32 {
+        self.0.nfs_gid as u32
+    }
+
+    /// Returns the ID of the device containing the file.
+    pub fn dev(&self) -> u64 {
+        self.0.nfs_dev
+    }
+
+    /// Returns the inode number of the file.
+    pub fn ino(&self) -> u64 {
+        self.0.nfs_ino
+    }
+
+    /// Returns the number of hard links to the file.
+    pub fn nlink(&self) -> u64 {
+        self.0.nfs_nlink
+    }
+
+    /// Returns the last modification time of the file.
+    ///
+    /// Like [`std::fs::Metadata::modified`], fails if the time can't be represented as a
+    /// [`SystemTime`].
+    pub fn modified(&self) -> io::Result<SystemTime> {
+        system_time(self.0.nfs_mtime, self.0.nfs_mtime_nsec)
+    }
+
+    /// Returns the last access time of the file.
+    pub fn accessed(&self) -> io::Result<SystemTime> {
+        system_time(self.0.nfs_atime, self.0.nfs_atime_nsec)
+    }
+
+    /// Returns the last status change time of the file, i.e. the last time its metadata or
+    /// contents changed.
+    ///
+    /// NFS doesn't track file creation time, which is why there is no `created`.
+    pub fn changed(&self) -> io::Result<SystemTime> {
+        system_time(self.0.nfs_ctime, self.0.nfs_ctime_nsec)
+    }
+}
+
+impl From<libnfs::nfs_stat_64> for Metadata {
+    fn from(stat: libnfs::nfs_stat_64) -> Metadata {
+        Metadata(stat)
+    }
+}
+
+impl std::fmt::Debug for Metadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metadata")
+            .field("file_type", &self.file_type())
+            .field("permissions", &self.permissions())
+            .field("len", &self.len())
+            .field("uid", &self.uid())
+            .field("gid", &self.gid())
+            .field("ino", &self.ino())
+            .field("modified", &self.modified().ok())
+            .field("accessed", &self.accessed().ok())
+            .field("changed", &self.changed().ok())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FsStats {
+    /// Returns the preferred I/O block size of the filesystem.
+    pub fn block_size(&self) -> u64 {
+        self.0.f_bsize
+    }
+
+    /// Returns the fundamental block size of the filesystem.
+    pub fn fragment_size(&self) -> u64 {
+        self.0.f_frsize
+    }
+
+    /// Returns the total number of blocks.
+    pub fn blocks(&self) -> u64 {
+        self.0.f_blocks
+    }
+
+    /// Returns the number of free blocks.
+    pub fn blocks_free(&self) -> u64 {
+        self.0.f_bfree
+    }
+
+    /// Returns the number of blocks available to unprivileged users.
+    pub fn blocks_available(&self) -> u64 {
+        self.0.f_bavail
+    }
+
+    /// Returns the total number of inodes.
+    pub fn files(&self) -> u64 {
+        self.0.f_files
+    }
+
+    /// Returns the number of free inodes.
+    pub fn files_free(&self) -> u64 {
+        self.0.f_ffree
+    }
+
+    /// Returns the number of inodes available to unprivileged users.
+    pub fn files_available(&self) -> u64 {
+        self.0.f_favail
+    }
+
+    /// Returns the maximum length of a file name.
+    pub fn name_max(&self) -> u64 {
+        self.0.f_namemax
+    }
+
+    /// Returns the total size of the filesystem in bytes.
+    pub fn total_space(&self) -> u64 {
+        self.blocks().saturating_mul(self.fragment_size())
+    }
+
+    /// Returns the free space of the filesystem in bytes.
+    pub fn free_space(&self) -> u64 {
+        self.blocks_free().saturating_mul(self.fragment_size())
+    }
+
+    /// Returns the space available to unprivileged users in bytes.
+    pub fn available_space(&self) -> u64 {
+        self.blocks_available().saturating_mul(self.fragment_size())
+    }
+}
+
+impl From<libnfs::nfs_statvfs_64> for FsStats {
+    fn from(stats: libnfs::nfs_statvfs_64) -> FsStats {
+        FsStats(stats)
+    }
+}
+
+impl std::fmt::Debug for FsStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FsStats")
+            .field("block_size", &self.block_size())
+            .field("fragment_size", &self.fragment_size())
+            .field("blocks", &self.blocks())
+            .field("blocks_free", &self.blocks_free())
+            .field("blocks_available", &self.blocks_available())
+            .field("files", &self.files())
+            .field("files_free", &self.files_free())
+            .field("files_available", &self.files_available())
+            .field("name_max", &self.name_max())
+            .finish()
+    }
+}
+
+fn system_time(secs: u64, nsecs: u64) -> io::Result<SystemTime> {
+    Duration::from_secs(secs)
+        .checked_add(Duration::from_nanos(nsecs))
+        .and_then(|since_epoch| UNIX_EPOCH.checked_add(since_epoch))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "file time out of range"))
+}
+
+impl FileType {
+    pub(crate) fn from_mode(mode: u64) -> FileType {
+        match SFlag::from_bits_truncate(mode as _) & SFlag::S_IFMT {
+            SFlag::S_IFREG => FileType::File,
+            SFlag::S_IFDIR => FileType::Dir,
+            SFlag::S_IFLNK => FileType::Symlink,
+            SFlag::S_IFBLK => FileType::BlockDevice,
+            SFlag::S_IFCHR => FileType::CharDevice,
+            SFlag::S_IFSOCK => FileType::Socket,
+            SFlag::S_IFIFO => FileType::Fifo,
+            _ => FileType::Unknown,
+        }
+    }
+
+    pub(crate) fn from_nf3(ty: u32) -> FileType {
+        match ty {
+            libnfs::ftype3_NF3REG => FileType::File,
+            libnfs::ftype3_NF3DIR => FileType::Dir,
+            libnfs::ftype3_NF3LNK => FileType::Symlink,
+            libnfs::ftype3_NF3BLK => FileType::BlockDevice,
+            libnfs::ftype3_NF3CHR => FileType::CharDevice,
+            libnfs::ftype3_NF3SOCK => FileType::Socket,
+            libnfs::ftype3_NF3FIFO => FileType::Fifo,
+            _ => FileType::Unknown,
+        }
+    }
+
+    pub(crate) fn to_mode(self) -> u64 {
+        let flag = match self {
+            FileType::File => SFlag::S_IFREG,
+            FileType::Dir => SFlag::S_IFDIR,
+            FileType::Symlink => SFlag::S_IFLNK,
+            FileType::BlockDevice => SFlag::S_IFBLK,
+            FileType::CharDevice => SFlag::S_IFCHR,
+            FileType::Socket => SFlag::S_IFSOCK,
+            FileType::Fifo => SFlag::S_IFIFO,
+            FileType::Unknown => SFlag::empty(),
+        };
+
+        flag.bits() as u64
+    }
+
+    /// Returns `true` if the file is a regular file.
+    pub fn is_file(&self) -> bool {
+        *self == FileType::File
+    }
+
+    /// Returns `true` if the file is a directory.
+    pub fn is_dir(&self) -> bool {
+        *self == FileType::Dir
+    }
+
+    /// Returns `true` if the file is a symbolic link.
+    pub fn is_symlink(&self) -> bool {
+        *self == FileType::Symlink
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(mode: u32) -> Metadata {
+        let mut stat: libnfs::nfs_stat_64 = unsafe { std::mem::zeroed() };
+        stat.nfs_mode = mode as u64;
+        stat.nfs_size = 42;
+        stat.nfs_mtime = 1_000_000;
+        stat.nfs_mtime_nsec = 500;
+        Metadata(stat)
+    }
+
+    #[test]
+    fn regular_file() {
+        let md = metadata(SFlag::S_IFREG.bits() | 0o640);
+        assert!(md.is_file());
+        assert!(!md.is_dir());
+        assert_eq!(md.permissions(), Mode::from_bits_truncate(0o640));
+        assert_eq!(md.len(), 42);
+        assert_eq!(
+            md.modified().unwrap(),
+            UNIX_EPOCH + Duration::new(1_000_000, 500)
+        );
+    }
+
+    #[test]
+    fn time_out_of_range() {
+        let mut md = metadata(SFlag::S_IFREG.bits());
+        md.0.nfs_atime = u64::MAX;
+        md.0.nfs_atime_nsec = u64::MAX;
+        assert_eq!(
+            md.accessed().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn directory() {
+        let md = metadata(SFlag::S_IFDIR.bits() | 0o755);
+        assert!(md.is_dir());
+        assert_eq!(md.file_type(), FileType::Dir);
+    }
+
+    #[test]
+    fn nf3_type_to_mode() {
+        let ty = FileType::from_nf3(libnfs::ftype3_NF3LNK);
+        assert_eq!(FileType::from_mode(ty.to_mode() | 0o777), FileType::Symlink);
+    }
+}
@@ -0,0 +1,195 @@
+use nix::{errno::Errno, fcntl::OFlag, sys::stat::Mode};
+use std::path::Path;
+
+/// Options and flags used to configure how a file is opened.
+///
+/// Mirrors [`tokio::fs::OpenOptions`]: every option defaults to `false`, and the combination is
+/// validated when the file is opened.
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+    mode: Mode,
+}
+
+impl OpenOptions {
+    /// Creates a blank set of options, with the creation mode set to `0o666`.
+    pub fn new() -> OpenOptions {
+        OpenOptions {
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+            mode: Mode::from_bits_truncate(0o666),
+        }
+    }
+
+    /// Sets the option for read access.
+    pub fn read(&mut self, read: bool) -> &mut OpenOptions {
+        self.read = read;
+        self
+    }
+
+    /// Sets the option for write access.
+    pub fn write(&mut self, write: bool) -> &mut OpenOptions {
+        self.write = write;
+        self
+    }
+
+    /// Sets the option for append mode. Implies write access.
+    pub fn append(&mut self, append: bool) -> &mut OpenOptions {
+        self.append = append;
+        self
+    }
+
+    /// Sets the option for truncating an existing file. Requires write access.
+    pub fn truncate(&mut self, truncate: bool) -> &mut OpenOptions {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Sets the option to create the file if it doesn't exist. Requires write access.
+    pub fn create(&mut self, create: bool) -> &mut OpenOptions {
+        self.create = create;
+        self
+    }
+
+    /// Sets the option to create the file, failing if it already exists. Requires write access
+    /// and takes precedence over [`create`](OpenOptions::create) and
+    /// [`truncate`](OpenOptions::truncate).
+    pub fn create_new(&mut self, create_new: bool) -> &mut OpenOptions {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Sets the permissions of a newly created file.
+    pub fn mode(&mut self, mode: Mode) -> &mut OpenOptions {
+        self.mode = mode;
+        self
+    }
+
+    /// Opens the file at `path` on `client` with these options.
+    pub async fn open<P: AsRef<Path>>(
+        &self,
+        client: &crate::Client,
+        path: P,
+    ) -> crate::Result<crate::File> {
+        client.open_with_flags(path, self.flags()?, self.mode).await
+    }
+
+    /// Opens the file at `path` on a blocking `client` with these options.
+    pub fn open_blocking<P: AsRef<Path>>(
+        &self,
+        client: &crate::blocking::Client,
+        path: P,
+    ) -> crate::Result<crate::blocking::File> {
+        client.open_with_flags(path, self.flags()?, self.mode)
+    }
+
+    fn flags(&self) -> crate::Result<OFlag> {
+        let mut flags = match (self.read, self.write || self.append) {
+            (true, false) => OFlag::O_RDONLY,
+            (false, true) => OFlag::O_WRONLY,
+            (true, true) => OFlag::O_RDWR,
+            (false, false) => return Err(invalid("no access mode set")),
+        };
+
+        if !(self.write || self.append) && (self.truncate || self.create || self.create_new) {
+            return Err(invalid(
+                "creating or truncating a file requires write access",
+            ));
+        }
+        if self.append && self.truncate && !self.create_new {
+            return Err(invalid("append and truncate are mutually exclusive"));
+        }
+
+        if self.append {
+            flags |= OFlag::O_APPEND;
+        }
+        if self.create_new {
+            flags |= OFlag::O_CREAT | OFlag::O_EXCL;
+        } else {
+            if self.create {
+                flags |= OFlag::O_CREAT;
+            }
+            if self.truncate {
+                flags |= OFlag::O_TRUNC;
+            }
+        }
+
+        Ok(flags)
+    }
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions::new()
+    }
+}
+
+fn invalid(msg: &str) -> crate::Error {
+    crate::error::nfs(format!("invalid open options: {msg}"), Errno::EINVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_modes() {
+        assert_eq!(
+            OpenOptions::new().read(true).flags().unwrap(),
+            OFlag::O_RDONLY
+        );
+        assert_eq!(
+            OpenOptions::new().write(true).flags().unwrap(),
+            OFlag::O_WRONLY
+        );
+        assert_eq!(
+            OpenOptions::new().read(true).append(true).flags().unwrap(),
+            OFlag::O_RDWR | OFlag::O_APPEND
+        );
+    }
+
+    #[test]
+    fn creation() {
+        assert_eq!(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .flags()
+                .unwrap(),
+            OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC
+        );
+        assert_eq!(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .create_new(true)
+                .flags()
+                .unwrap(),
+            OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_EXCL
+        );
+    }
+
+    #[test]
+    fn invalid_combinations() {
+        let err = OpenOptions::new().flags().unwrap_err();
+        assert_eq!(err.into_io().kind(), std::io::ErrorKind::InvalidInput);
+
+        assert!(OpenOptions::new().read(true).create(true).flags().is_err());
+        assert!(OpenOptions::new()
+            .append(true)
+            .truncate(true)
+            .flags()
+            .is_err());
+    }
+}
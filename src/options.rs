@@ -0,0 +1,280 @@
+use crate::client::Context;
+use libnfs_sys as libnfs;
+use std::{ffi::CString, fmt::Display, str::FromStr};
+use url::Url;
+
+/// NFS protocol version to use for the mount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V3,
+    V4,
+}
+
+impl Version {
+    fn number(self) -> i32 {
+        match self {
+            Version::V3 => 3,
+            Version::V4 => 4,
+        }
+    }
+}
+
+/// Mount options recognised by libnfs in the query string of an NFS URL.
+///
+/// Every URL passed to [`Client::mount`](crate::Client::mount) is validated against this set,
+/// so a typo in an option name or a malformed value is reported before connecting to the
+/// server.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MountOptions {
+    /// User ID used for AUTH_UNIX credentials (`uid`).
+    pub uid: Option<u32>,
+    /// Group ID used for AUTH_UNIX credentials (`gid`).
+    pub gid: Option<u32>,
+    /// NFS protocol version (`version`).
+    pub version: Option<Version>,
+    /// Port of the NFS service, skipping the portmapper lookup (`nfsport`).
+    pub nfsport: Option<u16>,
+    /// Port of the MOUNT service, skipping the portmapper lookup (`mountport`).
+    pub mountport: Option<u16>,
+    /// Size of the read-ahead window in bytes (`readahead`).
+    pub readahead: Option<u32>,
+    /// Number of pages in the per-file page cache (`pagecache`).
+    pub pagecache: Option<u32>,
+    /// Number of reconnect attempts, negative to retry forever (`autoreconnect`).
+    pub auto_reconnect: Option<i32>,
+    /// Number of SYN retransmits when connecting (`tcp-syncnt`).
+    pub tcp_syncnt: Option<u32>,
+    /// Whether the directory cache is enabled (`dircache`).
+    pub dircache: Option<bool>,
+    /// Whether nested exports are traversed automatically (`auto-traverse-mounts`).
+    pub auto_traverse_mounts: Option<bool>,
+    /// Network interface to bind to (`if`).
+    pub interface: Option<String>,
+    /// libnfs debug level (`debug`).
+    pub debug: Option<u32>,
+}
+
+impl MountOptions {
+    /// Parses and validates the query string of `url`.
+    pub fn from_url(url: &Url) -> crate::Result<MountOptions> {
+        let mut options = MountOptions::default();
+
+        for (key, val) in url.query_pairs() {
+            match &*key {
+                "uid" => options.uid = Some(parse(&key, &val)?),
+                "gid" => options.gid = Some(parse(&key, &val)?),
+                "version" => {
+                    options.version = Some(match &*val {
+                        "3" => Version::V3,
+                        "4" => Version::V4,
+                        _ => return Err(invalid(&key, &val)),
+                    })
+                }
+                "nfsport" => options.nfsport = Some(parse(&key, &val)?),
+                "mountport" => options.mountport = Some(parse(&key, &val)?),
+                "readahead" => options.readahead = Some(parse(&key, &val)?),
+                "pagecache" => options.pagecache = Some(parse(&key, &val)?),
+                "autoreconnect" => options.auto_reconnect = Some(parse(&key, &val)?),
+                "tcp-syncnt" => options.tcp_syncnt = Some(parse(&key, &val)?),
+                "dircache" => options.dircache = Some(parse_bool(&key, &val)?),
+                "auto-traverse-mounts" => {
+                    options.auto_traverse_mounts = Some(parse_bool(&key, &val)?)
+                }
+                "if" => {
+                    if val.is_empty() {
+                        return Err(invalid(&key, &val));
+                    }
+                    options.interface = Some(val.into_owned())
+                }
+                "debug" => options.debug = Some(parse(&key, &val)?),
+                _ => return Err(crate::error::url(format!("unknown mount option `{key}`"))),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Returns `base` with its query string replaced by these options.
+    pub fn to_url<T: crate::IntoUrl>(&self, base: T) -> crate::Result<Url> {
+        let mut url = base.into_url()?;
+
+        url.set_query(None);
+        let pairs = self.pairs();
+        if !pairs.is_empty() {
+            url.query_pairs_mut().extend_pairs(pairs);
+        }
+
+        Ok(url)
+    }
+
+    fn pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = Vec::new();
+
+        fn push<T: ToString>(
+            pairs: &mut Vec<(&'static str, String)>,
+            key: &'static str,
+            val: &Option<T>,
+        ) {
+            if let Some(val) = val {
+                pairs.push((key, val.to_string()));
+            }
+        }
+
+        push(&mut pairs, "uid", &self.uid);
+        push(&mut pairs, "gid", &self.gid);
+        push(&mut pairs, "version", &self.version.map(Version::number));
+        push(&mut pairs, "nfsport", &self.nfsport);
+        push(&mut pairs, "mountport", &self.mountport);
+        push(&mut pairs, "readahead", &self.readahead);
+        push(&mut pairs, "pagecache", &self.pagecache);
+        push(&mut pairs, "autoreconnect", &self.auto_reconnect);
+        push(&mut pairs, "tcp-syncnt", &self.tcp_syncnt);
+        push(&mut pairs, "dircache", &self.dircache.map(u8::from));
+        push(
+            &mut pairs,
+            "auto-traverse-mounts",
+            &self.auto_traverse_mounts.map(u8::from),
+        );
+        push(&mut pairs, "if", &self.interface);
+        push(&mut pairs, "debug", &self.debug);
+
+        pairs
+    }
+
+    pub(crate) unsafe fn apply(&self, context: &Context) -> crate::Result<()> {
+        if let Some(uid) = self.uid {
+            libnfs::nfs_set_uid(context.nfs, uid as i32);
+        }
+        if let Some(gid) = self.gid {
+            libnfs::nfs_set_gid(context.nfs, gid as i32);
+        }
+        if let Some(version) = self.version {
+            context.check_retcode(libnfs::nfs_set_version(context.nfs, version.number()))?;
+        }
+        if let Some(port) = self.nfsport {
+            libnfs::nfs_set_nfsport(context.nfs, port as i32);
+        }
+        if let Some(port) = self.mountport {
+            libnfs::nfs_set_mountport(context.nfs, port as i32);
+        }
+        if let Some(size) = self.readahead {
+            libnfs::nfs_set_readahead(context.nfs, size);
+        }
+        if let Some(pages) = self.pagecache {
+            libnfs::nfs_set_pagecache(context.nfs, pages);
+        }
+        if let Some(retries) = self.auto_reconnect {
+            libnfs::nfs_set_autoreconnect(context.nfs, retries);
+        }
+        if let Some(count) = self.tcp_syncnt {
+            libnfs::nfs_set_tcp_syncnt(context.nfs, count.try_into().unwrap_or(i32::MAX));
+        }
+        if let Some(enabled) = self.dircache {
+            libnfs::nfs_set_dircache(context.nfs, enabled as i32);
+        }
+        if let Some(enabled) = self.auto_traverse_mounts {
+            libnfs::nfs_set_auto_traverse_mounts(context.nfs, enabled as i32);
+        }
+        if let Some(ref interface) = self.interface {
+            let interface = CString::new(interface.as_str())
+                .map_err(|e| crate::error::nfs("invalid interface name", e))?;
+            libnfs::nfs_set_interface(context.nfs, interface.as_ptr());
+        }
+        if let Some(level) = self.debug {
+            libnfs::nfs_set_debug(context.nfs, level.try_into().unwrap_or(i32::MAX));
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid(key: &str, val: &str) -> crate::Error {
+    crate::error::url(format!("invalid value `{val}` for mount option `{key}`"))
+}
+
+fn parse<T>(key: &str, val: &str) -> crate::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    val.parse().map_err(|_| invalid(key, val))
+}
+
+fn parse_bool(key: &str, val: &str) -> crate::Result<bool> {
+    match val {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(invalid(key, val)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(query: &str) -> crate::Result<MountOptions> {
+        MountOptions::from_url(&Url::parse(&format!("nfs://127.0.0.1/share?{query}")).unwrap())
+    }
+
+    #[test]
+    fn valid_options() {
+        let opts =
+            options("uid=1000&gid=100&version=4&mountport=11111&dircache=0&if=eth0").unwrap();
+        assert_eq!(
+            opts,
+            MountOptions {
+                uid: Some(1000),
+                gid: Some(100),
+                version: Some(Version::V4),
+                mountport: Some(11111),
+                dircache: Some(false),
+                interface: Some("eth0".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_option() {
+        let err = options("vers=4").unwrap_err();
+        assert_eq!(err.to_string(), "URL error: unknown mount option `vers`");
+    }
+
+    #[test]
+    fn invalid_value() {
+        let err = options("uid=").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "URL error: invalid value `` for mount option `uid`"
+        );
+
+        let err = options("version=2").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "URL error: invalid value `2` for mount option `version`"
+        );
+
+        let err = options("dircache=yes").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "URL error: invalid value `yes` for mount option `dircache`"
+        );
+    }
+
+    #[test]
+    fn to_url() {
+        let opts = MountOptions {
+            uid: Some(0),
+            version: Some(Version::V3),
+            auto_reconnect: Some(-1),
+            auto_traverse_mounts: Some(true),
+            ..Default::default()
+        };
+        let url = opts.to_url("nfs://127.0.0.1/share?gid=5").unwrap();
+        assert_eq!(
+            url.as_str(),
+            "nfs://127.0.0.1/share?uid=0&version=3&autoreconnect=-1&auto-traverse-mounts=1"
+        );
+        assert_eq!(MountOptions::from_url(&url).unwrap(), opts);
+    }
+}
@@ -0,0 +1,349 @@
+use futures_core::Stream;
+use nix::errno::Errno;
+use std::{
+    collections::VecDeque,
+    ffi::{OsStr, OsString},
+    future::{poll_fn, Future},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+const DEFAULT_CONCURRENCY: usize = 8;
+
+type Filter<'a> = Box<dyn FnMut(&WalkEntry) -> bool + Send + 'a>;
+type Listing<'a> = Pin<Box<dyn Future<Output = Listed> + Send + 'a>>;
+
+/// A recursive stream over a directory tree.
+///
+/// Returned by [`Client::walk`](crate::Client::walk). The root itself is yielded first at depth
+/// 0, and every directory is yielded before its contents. Apart from that, entries come in no
+/// particular order: up to [`concurrency`](Walk::concurrency) directories are listed at once
+/// over the client's connection and their entries are yielded as the listings complete.
+///
+/// A directory that fails to list is reported as an `Err` item and the walk carries on with the
+/// rest of the tree.
+pub struct Walk<'a> {
+    client: &'a crate::Client,
+    root: PathBuf,
+    max_depth: usize,
+    follow_links: bool,
+    concurrency: usize,
+    filter: Option<Filter<'a>>,
+    prune: Option<Filter<'a>>,
+
+    started: bool,
+    queue: VecDeque<Pending>,
+    listings: Vec<Listing<'a>>,
+    ready: VecDeque<crate::Result<WalkEntry>>,
+}
+
+/// An entry returned by the [`Walk`] stream.
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    path: PathBuf,
+    depth: usize,
+    metadata: crate::Metadata,
+}
+
+// Directory waiting to be listed.
+struct Pending {
+    path: PathBuf,
+    depth: usize,
+    ancestors: Option<Arc<Ancestor>>,
+}
+
+// Chain of directories leading to a directory, used to detect symlink loops.
+struct Ancestor {
+    id: (u64, u64),
+    parent: Option<Arc<Ancestor>>,
+}
+
+struct Listed {
+    ancestors: Option<Arc<Ancestor>>,
+    entries: crate::Result<Vec<WalkEntry>>,
+}
+
+impl<'a> Walk<'a> {
+    pub(crate) fn new(client: &'a crate::Client, root: PathBuf) -> Walk<'a> {
+        Walk {
+            client,
+            root,
+            max_depth: usize::MAX,
+            follow_links: false,
+            concurrency: DEFAULT_CONCURRENCY,
+            filter: None,
+            prune: None,
+            started: false,
+            queue: VecDeque::new(),
+            listings: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Sets the maximum depth to descend to. The root is at depth 0, so `max_depth(0)` yields
+    /// only the root itself.
+    pub fn max_depth(mut self, depth: usize) -> Walk<'a> {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Follows symbolic links, yielding the metadata of their targets and descending into
+    /// linked directories. A link pointing back to one of its own ancestors is reported as an
+    /// `ELOOP` error instead of being followed. Dangling links are yielded as is.
+    pub fn follow_links(mut self, follow: bool) -> Walk<'a> {
+        self.follow_links = follow;
+        self
+    }
+
+    /// Sets the number of directories listed concurrently. Defaults to 8.
+    pub fn concurrency(mut self, n: usize) -> Walk<'a> {
+        self.concurrency = n.max(1);
+        self
+    }
+
+    /// Yields only entries for which `filter` returns `true`. Directories that are filtered out
+    /// are still descended into.
+    pub fn filter<F>(mut self, filter: F) -> Walk<'a>
+    where
+        F: FnMut(&WalkEntry) -> bool + Send + 'a,
+    {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Doesn't descend into directories for which `prune` returns `true`. The directories
+    /// themselves are still yielded.
+    pub fn prune<F>(mut self, prune: F) -> Walk<'a>
+    where
+        F: FnMut(&WalkEntry) -> bool + Send + 'a,
+    {
+        self.prune = Some(Box::new(prune));
+        self
+    }
+
+    fn start(&mut self) {
+        let client = self.client;
+        let root = self.root.clone();
+        let follow_links = self.follow_links;
+
+        self.listings.push(Box::pin(async move {
+            let metadata = client.stat(&root).await;
+
+            // The root starts the chain, so that links pointing back to it are caught right away
+            let ancestors = match metadata {
+                Ok(ref metadata) if follow_links => Some(Arc::new(Ancestor {
+                    id: (metadata.dev(), metadata.ino()),
+                    parent: None,
+                })),
+                _ => None,
+            };
+            let entries = metadata.map(|metadata| {
+                vec![WalkEntry {
+                    path: root,
+                    depth: 0,
+                    metadata,
+                }]
+            });
+
+            Listed { ancestors, entries }
+        }));
+    }
+
+    fn list(&self, dir: Pending) -> Listing<'a> {
+        let client = self.client;
+        let follow_links = self.follow_links;
+
+        Box::pin(async move {
+            let entries = async {
+                let mut read_dir = client.read_dir(&dir.path).await?;
+                let mut entries = Vec::new();
+
+                while let Some(entry) = poll_fn(|cx| Pin::new(&mut read_dir).poll_next(cx)).await {
+                    let mut metadata = entry.metadata();
+                    if follow_links && metadata.is_symlink() {
+                        if let Ok(target) = client.stat(entry.path()).await {
+                            metadata = target;
+                        }
+                    }
+
+                    entries.push(WalkEntry {
+                        path: entry.path(),
+                        depth: dir.depth,
+                        metadata,
+                    });
+                }
+
+                Ok(entries)
+            }
+            .await;
+
+            Listed {
+                ancestors: dir.ancestors,
+                entries,
+            }
+        })
+    }
+
+    fn complete(&mut self, listed: Listed) {
+        let entries = match listed.entries {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.ready.push_back(Err(e));
+                return;
+            }
+        };
+
+        for entry in entries {
+            let descend = entry.metadata.is_dir()
+                && entry.depth < self.max_depth
+                && match self.prune {
+                    Some(ref mut prune) => !prune(&entry),
+                    None => true,
+                };
+
+            if descend {
+                let mut ancestors = None;
+
+                if self.follow_links && entry.depth == 0 {
+                    ancestors = listed.ancestors.clone();
+                } else if self.follow_links {
+                    let id = (entry.metadata.dev(), entry.metadata.ino());
+                    if is_loop(&listed.ancestors, id) {
+                        let err = crate::error::nfs("filesystem loop", Errno::ELOOP);
+                        self.ready.push_back(Err(err.with_context(
+                            "walk",
+                            Some(&entry.path),
+                            self.client.url(),
+                        )));
+                        continue;
+                    }
+
+                    ancestors = Some(Arc::new(Ancestor {
+                        id,
+                        parent: listed.ancestors.clone(),
+                    }));
+                }
+
+                self.queue.push_back(Pending {
+                    path: entry.path.clone(),
+                    depth: entry.depth + 1,
+                    ancestors,
+                });
+            }
+
+            let keep = match self.filter {
+                Some(ref mut filter) => filter(&entry),
+                None => true,
+            };
+
+            if keep {
+                self.ready.push_back(Ok(entry));
+            }
+        }
+    }
+}
+
+fn is_loop(mut ancestor: &Option<Arc<Ancestor>>, id: (u64, u64)) -> bool {
+    while let Some(a) = ancestor {
+        if a.id == id {
+            return true;
+        }
+        ancestor = &a.parent;
+    }
+
+    false
+}
+
+impl Stream for Walk<'_> {
+    type Item = crate::Result<WalkEntry>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let me = self.get_mut();
+
+        if !me.started {
+            me.started = true;
+            me.start();
+        }
+
+        loop {
+            if let Some(item) = me.ready.pop_front() {
+                return Poll::Ready(Some(item));
+            }
+
+            while me.listings.len() < me.concurrency {
+                match me.queue.pop_front() {
+                    Some(dir) => {
+                        let listing = me.list(dir);
+                        me.listings.push(listing);
+                    }
+                    None => break,
+                }
+            }
+
+            if me.listings.is_empty() {
+                return Poll::Ready(None);
+            }
+
+            let mut progress = false;
+            let mut i = 0;
+            while i < me.listings.len() {
+                match me.listings[i].as_mut().poll(cx) {
+                    Poll::Ready(listed) => {
+                        drop(me.listings.swap_remove(i));
+                        me.complete(listed);
+                        progress = true;
+                    }
+                    Poll::Pending => i += 1,
+                }
+            }
+
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl WalkEntry {
+    /// Returns the full path to the entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Consumes the entry, returning its path.
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// Returns the bare file name of the entry.
+    pub fn file_name(&self) -> OsString {
+        self.path
+            .file_name()
+            .map(OsStr::to_os_string)
+            .unwrap_or_default()
+    }
+
+    /// Returns the depth of the entry relative to the root of the walk.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the type of the entry.
+    pub fn file_type(&self) -> crate::FileType {
+        self.metadata.file_type()
+    }
+
+    /// Returns the metadata of the entry. When following links, this is the metadata of the
+    /// link target.
+    pub fn metadata(&self) -> crate::Metadata {
+        self.metadata
+    }
+}
+
+impl AsRef<Path> for WalkEntry {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
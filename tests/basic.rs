@@ -1,8 +1,13 @@
 mod support;
 use support::*;
 
-use nix::{fcntl::OFlag, sys::stat::Mode, unistd::AccessFlags};
-use std::io::ErrorKind;
+use futures_util::StreamExt;
+use nix::{sys::stat::Mode, unistd::AccessFlags};
+use std::{
+    io::ErrorKind,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
 
 #[tokio::test]
 async fn directories() {
@@ -38,11 +43,7 @@ async fn directories() {
         .stat(&dir)
         .await
         .expect("stat() non-Ok for existing dir");
-    assert_eq!(
-        Mode::from_bits_truncate(st.nfs_mode as u32),
-        perms,
-        "invalid mode"
-    );
+    assert_eq!(st.permissions(), perms, "invalid mode");
 
     // Check if we can remove the directory
     client
@@ -67,8 +68,11 @@ async fn files() {
     let name = rand_name();
     let perms = Mode::from_bits_truncate(0o644);
     // Check if we can successfully create a file
-    let file = client
-        .open(&name, OFlag::O_CREAT, perms.clone())
+    let file = nfs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .mode(perms)
+        .open(&client, &name)
         .await
         .expect("failed to create file");
 
@@ -83,11 +87,7 @@ async fn files() {
     );
 
     let st = file.stat().await.expect("stat() non-Ok for existing file");
-    assert_eq!(
-        Mode::from_bits_truncate(st.nfs_mode as u32),
-        perms,
-        "invalid mode"
-    );
+    assert_eq!(st.permissions(), perms, "invalid mode");
 
     drop(file);
 
@@ -103,3 +103,665 @@ async fn files() {
 
     client.umount().await.expect("failed to umount");
 }
+
+#[tokio::test]
+async fn read_dir() {
+    let client = client().await;
+
+    let dir = rand_name();
+    client
+        .mkdir(&dir, Mode::from_bits_truncate(0o755))
+        .await
+        .expect("failed to create directory");
+
+    let subdir = Path::new(&dir).join(rand_name());
+    client
+        .mkdir(&subdir, Mode::from_bits_truncate(0o755))
+        .await
+        .expect("failed to create subdirectory");
+
+    let file = Path::new(&dir).join(rand_name());
+    drop(
+        nfs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .mode(Mode::from_bits_truncate(0o644))
+            .open(&client, &file)
+            .await
+            .expect("failed to create file"),
+    );
+
+    let mut entries = client
+        .read_dir(&dir)
+        .await
+        .expect("failed to open directory")
+        .collect::<Vec<_>>()
+        .await;
+    entries.sort_by_key(|e| e.path());
+    let mut expected = vec![subdir.clone(), file.clone()];
+    expected.sort();
+    assert_eq!(
+        entries.iter().map(|e| e.path()).collect::<Vec<_>>(),
+        expected,
+        "invalid directory entries"
+    );
+
+    for entry in &entries {
+        let st = client.stat(entry.path()).await.expect("stat() failed");
+        assert_eq!(entry.ino(), st.ino(), "invalid inode");
+        if entry.path() == subdir {
+            assert!(entry.file_type().is_dir(), "subdirectory is not a dir");
+        } else {
+            assert!(entry.file_type().is_file(), "file is not a file");
+        }
+    }
+
+    client.unlink(&file).await.expect("failed to remove file");
+    client
+        .rmdir(&subdir)
+        .await
+        .expect("failed to remove subdirectory");
+    client
+        .rmdir(&dir)
+        .await
+        .expect("failed to remove directory");
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn rename() {
+    let client = client().await;
+
+    let from = rand_name();
+    let to = rand_name();
+    drop(
+        nfs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .mode(Mode::from_bits_truncate(0o644))
+            .open(&client, &from)
+            .await
+            .expect("failed to create file"),
+    );
+
+    client
+        .rename(&from, &to)
+        .await
+        .expect("failed to rename file");
+
+    let err = client
+        .access(&from)
+        .await
+        .expect_err("access() Ok for renamed file");
+    assert_eq!(err.into_io().kind(), ErrorKind::NotFound);
+    client
+        .access(&to)
+        .await
+        .expect("access() non-Ok for renamed file");
+
+    client.unlink(&to).await.expect("failed to remove file");
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn links() {
+    let client = client().await;
+
+    let name = rand_name();
+    drop(
+        nfs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .mode(Mode::from_bits_truncate(0o644))
+            .open(&client, &name)
+            .await
+            .expect("failed to create file"),
+    );
+
+    // Hard link shares the inode with the original file
+    let hard = rand_name();
+    client
+        .link(&name, &hard)
+        .await
+        .expect("failed to create hard link");
+    let st = client.stat(&name).await.expect("stat() failed");
+    let hard_st = client.stat(&hard).await.expect("stat() failed");
+    assert_eq!(st.ino(), hard_st.ino(), "inode mismatch");
+    assert_eq!(hard_st.nlink(), 2, "invalid link count");
+
+    // Symbolic link points to the original file
+    let soft = rand_name();
+    client
+        .symlink(&name, &soft)
+        .await
+        .expect("failed to create symlink");
+    let target = client
+        .readlink(&soft)
+        .await
+        .expect("failed to read symlink");
+    assert_eq!(target, Path::new(&name));
+
+    // `stat` follows the symlink while `lstat` doesn't
+    let st = client.stat(&soft).await.expect("stat() failed");
+    assert!(st.is_file(), "stat() didn't follow symlink");
+    let lst = client.lstat(&soft).await.expect("lstat() failed");
+    assert!(lst.is_symlink(), "lstat() followed symlink");
+
+    client
+        .unlink(&soft)
+        .await
+        .expect("failed to remove symlink");
+    client
+        .unlink(&hard)
+        .await
+        .expect("failed to remove hard link");
+    client.unlink(&name).await.expect("failed to remove file");
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn set_attributes() {
+    let client = client().await;
+
+    let name = rand_name();
+    drop(
+        nfs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .mode(Mode::from_bits_truncate(0o644))
+            .open(&client, &name)
+            .await
+            .expect("failed to create file"),
+    );
+
+    let perms = Mode::from_bits_truncate(0o600);
+    client
+        .chmod(&name, perms)
+        .await
+        .expect("failed to change mode");
+    let st = client.stat(&name).await.expect("stat() failed");
+    assert_eq!(st.permissions(), perms, "invalid mode");
+
+    // The test server squashes all users, so only a no-op chown is allowed
+    client
+        .chown(&name, st.uid(), st.gid())
+        .await
+        .expect("failed to change owner");
+
+    let accessed = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let modified = UNIX_EPOCH + Duration::from_secs(2_000_000);
+    client
+        .set_times(&name, accessed, modified)
+        .await
+        .expect("failed to set times");
+    let st = client.stat(&name).await.expect("stat() failed");
+    assert_eq!(st.accessed().ok(), Some(accessed), "invalid access time");
+    assert_eq!(
+        st.modified().ok(),
+        Some(modified),
+        "invalid modification time"
+    );
+
+    client
+        .truncate(&name, 4096)
+        .await
+        .expect("failed to truncate file");
+    let st = client.stat(&name).await.expect("stat() failed");
+    assert_eq!(st.len(), 4096, "invalid size");
+
+    client.unlink(&name).await.expect("failed to remove file");
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn statvfs() {
+    let client = client().await;
+
+    let stats = client.statvfs("/").await.expect("statvfs() failed");
+    assert!(stats.fragment_size() > 0, "invalid fragment size");
+    assert!(stats.blocks() > 0, "invalid total blocks");
+    assert!(stats.blocks_free() <= stats.blocks(), "invalid free blocks");
+    assert!(
+        stats.blocks_available() <= stats.blocks_free(),
+        "invalid available blocks"
+    );
+    assert!(stats.files_free() <= stats.files(), "invalid free inodes");
+    assert!(stats.name_max() > 0, "invalid max name length");
+    assert_eq!(
+        stats.total_space(),
+        stats.blocks() * stats.fragment_size(),
+        "invalid total space"
+    );
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn builder() {
+    let client = nfs::Client::builder()
+        .version(nfs::Version::V3)
+        .timeout(Duration::from_secs(30))
+        .readahead(128 * 1024)
+        .pagecache(256)
+        .auto_reconnect(Some(3))
+        .dircache(false)
+        .mount(server())
+        .await
+        .expect("failed to mount NFS server");
+
+    let st = client.stat("/").await.expect("stat() failed");
+    assert!(st.is_dir(), "root is not a directory");
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn async_backend() {
+    let client = nfs::Client::builder()
+        .backend(nfs::Backend::Async)
+        .mount(server())
+        .await
+        .expect("failed to mount NFS server");
+
+    let dir = rand_name();
+    client
+        .mkdir(&dir, Mode::S_IRWXU)
+        .await
+        .expect("mkdir() failed");
+
+    // Requests issued concurrently are pipelined on the same connection
+    let names = [rand_name(), rand_name(), rand_name(), rand_name()];
+    let paths: Vec<_> = names.iter().map(|n| Path::new(&dir).join(n)).collect();
+    tokio::try_join!(
+        client.mkdir(&paths[0], Mode::S_IRWXU),
+        client.mkdir(&paths[1], Mode::S_IRWXU),
+        client.mkdir(&paths[2], Mode::S_IRWXU),
+        client.mkdir(&paths[3], Mode::S_IRWXU),
+    )
+    .expect("concurrent mkdir() failed");
+
+    let (a, b) = tokio::try_join!(client.stat(&paths[0]), client.stat(&paths[3]))
+        .expect("concurrent stat() failed");
+    assert!(
+        a.is_dir() && b.is_dir(),
+        "created entries are not directories"
+    );
+
+    let mut entries = client
+        .read_dir(&dir)
+        .await
+        .expect("read_dir() failed")
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .await;
+    entries.sort();
+    let mut expected = names.to_vec();
+    expected.sort();
+    assert_eq!(entries, expected);
+
+    let file_path = Path::new(&dir).join(rand_name());
+    let file = nfs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&client, &file_path)
+        .await
+        .expect("open() failed");
+    assert_eq!(
+        file.write_at(0, b"hello").await.expect("write_at() failed"),
+        5
+    );
+    let mut buf = [0u8; 5];
+    assert_eq!(
+        file.read_at(0, &mut buf).await.expect("read_at() failed"),
+        5
+    );
+    assert_eq!(&buf, b"hello");
+    drop(file);
+
+    client.unlink(&file_path).await.expect("unlink() failed");
+    for path in &paths {
+        client.rmdir(path).await.expect("rmdir() failed");
+    }
+    client.rmdir(&dir).await.expect("rmdir() failed");
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn walk() {
+    let client = client().await;
+
+    let root = Path::new("/").join(rand_name());
+    client
+        .mkdir(&root, Mode::S_IRWXU)
+        .await
+        .expect("mkdir() failed");
+    client
+        .mkdir(root.join("a"), Mode::S_IRWXU)
+        .await
+        .expect("mkdir() failed");
+    client
+        .mkdir(root.join("a/b"), Mode::S_IRWXU)
+        .await
+        .expect("mkdir() failed");
+    client
+        .mkdir(root.join("skip"), Mode::S_IRWXU)
+        .await
+        .expect("mkdir() failed");
+    for file in ["a/f1", "a/b/f2", "skip/f3"] {
+        client.create(root.join(file)).await.expect("open() failed");
+    }
+    // Points back to its own ancestor
+    client
+        .symlink("..", root.join("a/b/up"))
+        .await
+        .expect("symlink() failed");
+
+    let relative = |e: nfs::WalkEntry| {
+        (
+            e.path()
+                .strip_prefix(&root)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+            e.depth(),
+        )
+    };
+
+    let mut entries = client
+        .walk(&root)
+        .concurrency(2)
+        .prune(|e| e.file_name() == "skip")
+        .filter(|e| !e.file_type().is_symlink())
+        .map(|e| relative(e.expect("walk failed")))
+        .collect::<Vec<_>>()
+        .await;
+    entries.sort();
+    assert_eq!(
+        entries,
+        [
+            ("".to_string(), 0),
+            ("a".to_string(), 1),
+            ("a/b".to_string(), 2),
+            ("a/b/f2".to_string(), 3),
+            ("a/f1".to_string(), 2),
+            ("skip".to_string(), 1),
+        ]
+    );
+
+    let entries = client.walk(&root).max_depth(1).collect::<Vec<_>>().await;
+    assert_eq!(entries.len(), 3);
+
+    // Following links must detect the loop instead of descending forever
+    let results = client
+        .walk(&root)
+        .follow_links(true)
+        .collect::<Vec<_>>()
+        .await;
+    let loops = results
+        .iter()
+        .filter_map(|r| r.as_ref().err())
+        .filter(|e| e.to_string().contains("filesystem loop"))
+        .collect::<Vec<_>>();
+    assert_eq!(loops.len(), 1);
+    assert_eq!(
+        loops[0].raw_os_error(),
+        Some(nix::errno::Errno::ELOOP as i32)
+    );
+    assert_eq!(loops[0].operation(), Some("walk"));
+    assert_eq!(loops[0].path(), Some(root.join("a/b/up").as_path()));
+    assert!(loops[0].url().is_some());
+
+    // A link to the root is caught without walking the root a second time
+    client
+        .symlink("..", root.join("skip/root"))
+        .await
+        .expect("symlink() failed");
+    let results = client
+        .walk(&root)
+        .follow_links(true)
+        .prune(|e| e.file_name() == "a")
+        .collect::<Vec<_>>()
+        .await;
+    let loops = results
+        .iter()
+        .filter(|r| matches!(r, Err(e) if e.to_string().contains("filesystem loop")))
+        .count();
+    assert_eq!(loops, 1);
+    // The root, a, skip, skip/f3 and the loop
+    assert_eq!(results.len(), 5);
+
+    for file in ["a/b/up", "a/b/f2", "a/f1", "skip/f3", "skip/root"] {
+        client
+            .unlink(root.join(file))
+            .await
+            .expect("unlink() failed");
+    }
+    for dir in ["a/b", "a", "skip"] {
+        client.rmdir(root.join(dir)).await.expect("rmdir() failed");
+    }
+    client.rmdir(&root).await.expect("rmdir() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn create_and_remove_dir_all() {
+    let client = client().await;
+
+    let root = Path::new("/").join(rand_name());
+    let deep = root.join("a/b/c");
+    client
+        .create_dir_all(&deep, Mode::S_IRWXU)
+        .await
+        .expect("create_dir_all() failed");
+    assert!(client.stat(&deep).await.expect("stat() failed").is_dir());
+
+    // Existing components are fine, including the whole path
+    client
+        .create_dir_all(&deep, Mode::S_IRWXU)
+        .await
+        .expect("create_dir_all() on existing path failed");
+
+    // Racing creators must both succeed
+    let other = root.join("x/y");
+    let (a, b) = tokio::join!(
+        client.create_dir_all(&other, Mode::S_IRWXU),
+        client.create_dir_all(&other, Mode::S_IRWXU),
+    );
+    a.expect("racing create_dir_all() failed");
+    b.expect("racing create_dir_all() failed");
+
+    for i in 0..20 {
+        client
+            .create(deep.join(format!("file{i}")))
+            .await
+            .expect("open() failed");
+    }
+    client
+        .symlink("a", root.join("link"))
+        .await
+        .expect("symlink() failed");
+
+    // A file in the way is reported with the offending component
+    let blocked = deep.join("file0");
+    let err = client
+        .create_dir_all(blocked.join("d"), Mode::S_IRWXU)
+        .await
+        .expect_err("create_dir_all() through a file succeeded");
+    assert_eq!(err.operation(), Some("create_dir_all"));
+    assert_eq!(err.path(), Some(blocked.as_path()));
+
+    client
+        .remove_dir_all(&root)
+        .await
+        .expect("remove_dir_all() failed");
+    let err = client
+        .stat(&root)
+        .await
+        .expect_err("stat() succeeded on removed directory");
+    assert_eq!(err.into_io().kind(), ErrorKind::NotFound);
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn error_kinds() {
+    let client = client().await;
+
+    let name = rand_name();
+    let err = client
+        .stat(&name)
+        .await
+        .expect_err("stat() of a missing file succeeded");
+    assert_eq!(err.kind(), nfs::ErrorKind::Nfs);
+    assert_eq!(err.operation(), Some("stat"));
+    assert_eq!(err.path(), Some(Path::new(&name)));
+    assert!(err.url().is_some());
+    assert!(err.to_string().starts_with(&format!("stat {name} on ")));
+    assert_eq!(err.nfs_status(), Some(nfs::NfsStatus::NoEnt));
+    assert!(err.is_not_found());
+    assert!(!err.is_permission_denied());
+
+    let Err(err) = nfs::Client::mount("http://localhost/export").await else {
+        panic!("mounted a non-NFS URL");
+    };
+    assert_eq!(err.kind(), nfs::ErrorKind::Url);
+    assert_eq!(err.nfs_status(), None);
+}
+
+#[tokio::test]
+async fn whole_file_helpers() {
+    let client = client().await;
+
+    let name = rand_name();
+    assert!(!client.try_exists(&name).await.expect("try_exists() failed"));
+
+    // Larger than any server transfer size to exercise chunking
+    let data = (0..3 * 1024 * 1024 + 7)
+        .map(|i| (i % 253) as u8)
+        .collect::<Vec<_>>();
+    client.write(&name, &data).await.expect("write() failed");
+    assert!(client.try_exists(&name).await.expect("try_exists() failed"));
+    assert_eq!(client.read(&name).await.expect("read() failed"), data);
+
+    // Overwriting truncates
+    client
+        .write(&name, "config = 1\n")
+        .await
+        .expect("write() failed");
+    assert_eq!(
+        client
+            .read_to_string(&name)
+            .await
+            .expect("read_to_string() failed"),
+        "config = 1\n"
+    );
+
+    client
+        .write(&name, [0xff, 0xfe])
+        .await
+        .expect("write() failed");
+    let err = client
+        .read_to_string(&name)
+        .await
+        .expect_err("read_to_string() accepted invalid UTF-8");
+    assert_eq!(err.into_io().kind(), ErrorKind::InvalidData);
+
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn shared_client() {
+    let client = client().await;
+    let clone = client.clone();
+
+    // Operations already running on a clone are waited for, later ones fail
+    let name = rand_name();
+    let create = tokio::spawn({
+        let clone = clone.clone();
+        let name = name.clone();
+        async move { clone.mkdir(&name, Mode::from_bits_truncate(0o755)).await }
+    });
+    client.umount().await.expect("failed to umount");
+    match create.await.unwrap() {
+        Ok(()) => {
+            let client = support::client().await;
+            client.rmdir(&name).await.expect("rmdir() failed");
+            client.umount().await.expect("failed to umount");
+        }
+        Err(err) => assert_eq!(err.into_io().kind(), ErrorKind::NotConnected),
+    }
+
+    let err = clone
+        .stat(".")
+        .await
+        .expect_err("stat() succeeded after umount");
+    assert_eq!(err.into_io().kind(), ErrorKind::NotConnected);
+    let err = client.umount().await.expect_err("umount() succeeded twice");
+    assert_eq!(err.raw_os_error(), Some(nix::errno::Errno::ENOTCONN as i32));
+}
+
+#[test]
+fn blocking_api() {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let client = nfs::blocking::Client::mount(server()).expect("failed to mount NFS server");
+
+    let dir = rand_name();
+    client
+        .create_dir_all(Path::new(&dir).join("sub"), Mode::from_bits_truncate(0o755))
+        .expect("create_dir_all() failed");
+
+    let name = Path::new(&dir).join("file");
+    let mut file = client.create(&name).expect("create() failed");
+    file.write_all(b"hello, world").expect("write_all() failed");
+    drop(file);
+
+    let mut file = nfs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open_blocking(&client, &name)
+        .expect("open_blocking() failed");
+    assert_eq!(file.seek(SeekFrom::Start(7)).expect("seek() failed"), 7);
+    file.write_all(b"NFS!!").expect("write_all() failed");
+    assert_eq!(file.seek(SeekFrom::End(-5)).expect("seek() failed"), 7);
+
+    let mut tail = String::new();
+    file.read_to_string(&mut tail)
+        .expect("read_to_string() failed");
+    assert_eq!(tail, "NFS!!");
+
+    let mut head = [0u8; 5];
+    assert_eq!(file.read_at(0, &mut head).expect("read_at() failed"), 5);
+    assert_eq!(&head, b"hello");
+    file.close().expect("close() failed");
+
+    assert_eq!(
+        client
+            .read_to_string(&name)
+            .expect("read_to_string() failed"),
+        "hello, NFS!!"
+    );
+
+    let mut entries = client
+        .read_dir(&dir)
+        .expect("read_dir() failed")
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(entries, ["file", "sub"]);
+
+    client.unlink(&name).expect("unlink() failed");
+    client
+        .rmdir(Path::new(&dir).join("sub"))
+        .expect("rmdir() failed");
+    client.rmdir(&dir).expect("rmdir() failed");
+    assert!(!client.try_exists(&dir).expect("try_exists() failed"));
+
+    client.umount().expect("umount() failed");
+}
@@ -1,8 +1,13 @@
 mod support;
 use support::*;
 
-use nix::{fcntl::OFlag, sys::stat::Mode};
-use tokio::io::{copy, AsyncReadExt, AsyncWriteExt};
+use nix::sys::stat::Mode;
+use std::{
+    io::{ErrorKind, SeekFrom},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+use tokio::io::{copy, AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
 #[tokio::test]
 async fn file_io() {
@@ -13,8 +18,11 @@ async fn file_io() {
     let name = rand_name();
     let perms = Mode::from_bits_truncate(0o644);
     // Check if we can successfully create a file
-    let mut wfile = client
-        .open(&name, OFlag::O_CREAT | OFlag::O_WRONLY, perms.clone())
+    let mut wfile = nfs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .mode(perms)
+        .open(&client, &name)
         .await
         .expect("failed to create file");
 
@@ -29,10 +37,7 @@ async fn file_io() {
     wfile.flush().await.expect("failed to flush data");
     drop(wfile);
 
-    let mut rfile = client
-        .open(&name, OFlag::O_RDONLY, perms.clone())
-        .await
-        .expect("failed to open file");
+    let mut rfile = client.open(&name).await.expect("failed to open file");
 
     let mut rdata = Vec::new();
     let read = rfile
@@ -54,3 +59,705 @@ async fn file_io() {
 
     client.umount().await.expect("failed to umount");
 }
+
+#[tokio::test]
+async fn file_seek() {
+    const DATA_LEN: usize = 64 * 1024;
+
+    let client = client().await;
+
+    let name = rand_name();
+    let perms = Mode::from_bits_truncate(0o644);
+    let mut file = nfs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .mode(perms)
+        .open(&client, &name)
+        .await
+        .expect("failed to create file");
+
+    let wdata = (0..DATA_LEN).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    file.write_all(&wdata).await.expect("failed to write data");
+    file.flush().await.expect("failed to flush data");
+
+    let pos = file
+        .seek(SeekFrom::Start(1000))
+        .await
+        .expect("failed to seek from start");
+    assert_eq!(pos, 1000);
+
+    let mut rdata = vec![0; 100];
+    file.read_exact(&mut rdata)
+        .await
+        .expect("failed to read data");
+    assert_eq!(rdata, wdata[1000..1100]);
+
+    // Buffered but unconsumed data must be accounted for
+    let pos = file
+        .seek(SeekFrom::Current(-50))
+        .await
+        .expect("failed to seek from current");
+    assert_eq!(pos, 1050);
+
+    file.read_exact(&mut rdata)
+        .await
+        .expect("failed to read data");
+    assert_eq!(rdata, wdata[1050..1150]);
+
+    let pos = file
+        .seek(SeekFrom::End(-100))
+        .await
+        .expect("failed to seek from end");
+    assert_eq!(pos, (DATA_LEN - 100) as u64);
+
+    let mut rdata = Vec::new();
+    file.read_to_end(&mut rdata)
+        .await
+        .expect("failed to read data");
+    assert_eq!(rdata, wdata[DATA_LEN - 100..]);
+    drop(file);
+
+    client
+        .unlink(&name)
+        .await
+        .expect("failed to remove the file");
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn file_positional_io() {
+    const CHUNK_LEN: usize = 16 * 1024;
+    const CHUNKS: usize = 8;
+
+    let client = client().await;
+
+    let name = rand_name();
+    let perms = Mode::from_bits_truncate(0o644);
+    let file = Arc::new(
+        nfs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .mode(perms)
+            .open(&client, &name)
+            .await
+            .expect("failed to create file"),
+    );
+
+    let chunk = |i: usize| vec![i as u8; CHUNK_LEN];
+
+    // Write chunks concurrently in reverse order
+    let writers = (0..CHUNKS)
+        .rev()
+        .map(|i| {
+            let file = Arc::clone(&file);
+            tokio::spawn(async move {
+                file.write_at((i * CHUNK_LEN) as u64, &chunk(i))
+                    .await
+                    .expect("failed to write chunk")
+            })
+        })
+        .collect::<Vec<_>>();
+    for writer in writers {
+        assert_eq!(writer.await.unwrap(), CHUNK_LEN);
+    }
+
+    // Read them back concurrently
+    let readers = (0..CHUNKS)
+        .map(|i| {
+            let file = Arc::clone(&file);
+            tokio::spawn(async move {
+                let mut buf = vec![0; CHUNK_LEN];
+                let n = file
+                    .read_at((i * CHUNK_LEN) as u64, &mut buf)
+                    .await
+                    .expect("failed to read chunk");
+                assert_eq!(n, CHUNK_LEN);
+                assert_eq!(buf, chunk(i));
+            })
+        })
+        .collect::<Vec<_>>();
+    for reader in readers {
+        reader.await.unwrap();
+    }
+
+    // Reading past the end returns a short read
+    let mut buf = vec![0; CHUNK_LEN];
+    let n = file
+        .read_at((CHUNKS * CHUNK_LEN - 10) as u64, &mut buf)
+        .await
+        .expect("failed to read last chunk");
+    assert_eq!(n, 10);
+
+    // Positional I/O doesn't touch the cursor
+    let mut file = Arc::into_inner(file).unwrap();
+    let mut rdata = vec![0; CHUNK_LEN];
+    file.read_exact(&mut rdata)
+        .await
+        .expect("failed to read data");
+    assert_eq!(rdata, chunk(0));
+    drop(file);
+
+    client
+        .unlink(&name)
+        .await
+        .expect("failed to remove the file");
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn file_set_attributes() {
+    let client = client().await;
+
+    let name = rand_name();
+    let file = nfs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .mode(Mode::from_bits_truncate(0o644))
+        .open(&client, &name)
+        .await
+        .expect("failed to create file");
+
+    let perms = Mode::from_bits_truncate(0o600);
+    file.set_permissions(perms)
+        .await
+        .expect("failed to change mode");
+    let st = file.stat().await.expect("stat() failed");
+    assert_eq!(st.permissions(), perms, "invalid mode");
+
+    // The test server squashes all users, so only a no-op chown is allowed
+    file.set_owner(st.uid(), st.gid())
+        .await
+        .expect("failed to change owner");
+
+    file.set_len(12345).await.expect("failed to set length");
+    let st = file.stat().await.expect("stat() failed");
+    assert_eq!(st.len(), 12345, "invalid size");
+
+    let accessed = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let modified = UNIX_EPOCH + Duration::from_secs(2_000_000);
+    file.set_times(accessed, modified)
+        .await
+        .expect("failed to set times");
+    let st = file.stat().await.expect("stat() failed");
+    assert_eq!(st.accessed().ok(), Some(accessed), "invalid access time");
+    assert_eq!(
+        st.modified().ok(),
+        Some(modified),
+        "invalid modification time"
+    );
+    drop(file);
+
+    client
+        .unlink(&name)
+        .await
+        .expect("failed to remove the file");
+
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn open_options() {
+    let client = client().await;
+
+    let name = rand_name();
+    write_with(
+        &client,
+        nfs::OpenOptions::new().write(true).create(true),
+        &name,
+        b"hello",
+    )
+    .await;
+
+    // create_new refuses to clobber an existing file
+    let Err(err) = nfs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&client, &name)
+        .await
+    else {
+        panic!("create_new() succeeded on existing file");
+    };
+    assert_eq!(err.into_io().kind(), ErrorKind::AlreadyExists);
+
+    write_with(
+        &client,
+        nfs::OpenOptions::new().append(true),
+        &name,
+        b", world",
+    )
+    .await;
+
+    let mut data = String::new();
+    client
+        .open(&name)
+        .await
+        .expect("open() failed")
+        .read_to_string(&mut data)
+        .await
+        .expect("read failed");
+    assert_eq!(data, "hello, world");
+
+    // Truncating without write access is rejected before reaching the server
+    let Err(err) = nfs::OpenOptions::new()
+        .read(true)
+        .truncate(true)
+        .open(&client, &name)
+        .await
+    else {
+        panic!("truncate without write access succeeded");
+    };
+    assert_eq!(err.into_io().kind(), ErrorKind::InvalidInput);
+
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn buffer_size() {
+    const DATA_LEN: usize = 8 * 1024 * 1024;
+    const SMALL_BUF: usize = 16 * 1024;
+
+    let client = client().await;
+
+    let name = rand_name();
+    let data = (0..DATA_LEN).map(|i| (i % 241) as u8).collect::<Vec<_>>();
+    client.write(&name, &data).await.expect("write() failed");
+
+    // Every call to `read` on an empty buffer is a single READ RPC, so counting the calls counts
+    // the round trips
+    let read_all = |file: nfs::File| async move {
+        let mut file = file;
+        let mut buf = vec![0u8; DATA_LEN];
+        let (mut calls, mut total) = (0, 0);
+        loop {
+            let n = file.read(&mut buf).await.expect("read failed");
+            if n == 0 {
+                break;
+            }
+            calls += 1;
+            total += n;
+        }
+        assert_eq!(total, DATA_LEN);
+        calls
+    };
+
+    let mut small = client.open(&name).await.expect("open() failed");
+    small.set_max_buf_size(SMALL_BUF);
+    let small_calls = read_all(small).await;
+    assert_eq!(small_calls, DATA_LEN / SMALL_BUF);
+
+    let default_calls = read_all(client.open(&name).await.expect("open() failed")).await;
+    assert!(
+        default_calls < small_calls,
+        "server-sized buffer took {default_calls} reads, expected fewer than {small_calls}"
+    );
+
+    // The client-wide setting applies to every file it opens
+    let small_client = nfs::Client::builder()
+        .max_buf_size(SMALL_BUF)
+        .mount(server())
+        .await
+        .expect("failed to mount NFS server");
+    let calls = read_all(small_client.open(&name).await.expect("open() failed")).await;
+    assert_eq!(calls, small_calls);
+    small_client.umount().await.expect("failed to umount");
+
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn write_behind() {
+    const DATA_LEN: usize = 4 * 1024 * 1024;
+
+    let client = client().await;
+
+    let name = rand_name();
+    let mut file = nfs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&client, &name)
+        .await
+        .expect("failed to create file");
+    file.set_max_buf_size(64 * 1024);
+    file.set_write_behind(8);
+
+    let wdata = (0..DATA_LEN).map(|i| (i % 239) as u8).collect::<Vec<_>>();
+    file.write_all(&wdata).await.expect("failed to write data");
+    file.flush().await.expect("failed to flush data");
+
+    // The cursor ends up after the data written behind
+    let pos = file
+        .stream_position()
+        .await
+        .expect("failed to get position");
+    assert_eq!(pos, DATA_LEN as u64);
+
+    // Overwrite a range in the middle, then append, without flushing in between
+    file.seek(SeekFrom::Start(1000))
+        .await
+        .expect("failed to seek");
+    file.write_all(&[0xaa; 5000])
+        .await
+        .expect("failed to write data");
+    file.seek(SeekFrom::End(0)).await.expect("failed to seek");
+    file.write_all(b"tail").await.expect("failed to write data");
+    file.flush().await.expect("failed to flush data");
+
+    let mut expected = wdata;
+    expected[1000..6000].fill(0xaa);
+    expected.extend_from_slice(b"tail");
+
+    file.rewind().await.expect("failed to rewind");
+    let mut rdata = Vec::new();
+    file.read_to_end(&mut rdata)
+        .await
+        .expect("failed to read data");
+    assert!(
+        rdata == expected,
+        "data read back differs from data written"
+    );
+    drop(file);
+
+    // Writes to a file opened for appending still land at its end
+    let mut file = nfs::OpenOptions::new()
+        .append(true)
+        .open(&client, &name)
+        .await
+        .expect("failed to open file for appending");
+    file.set_max_buf_size(4);
+    file.set_write_behind(8);
+    file.write_all(b"appended")
+        .await
+        .expect("failed to write data");
+    file.flush().await.expect("failed to flush data");
+    drop(file);
+
+    expected.extend_from_slice(b"appended");
+    let rdata = client.read(&name).await.expect("read() failed");
+    assert!(
+        rdata == expected,
+        "data read back differs from data appended"
+    );
+
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn read_ahead() {
+    const DATA_LEN: usize = 4 * 1024 * 1024 + 123;
+
+    let client = client().await;
+
+    let name = rand_name();
+    let data = (0..DATA_LEN).map(|i| (i % 233) as u8).collect::<Vec<_>>();
+    client.write(&name, &data).await.expect("write() failed");
+
+    let mut file = client.open(&name).await.expect("open() failed");
+    file.set_max_buf_size(64 * 1024);
+    file.set_read_ahead(4);
+
+    // Odd-sized reads straddle the buffers read ahead
+    let mut chunk = vec![0u8; 10_000];
+    for i in 0..100 {
+        file.read_exact(&mut chunk).await.expect("read failed");
+        assert!(
+            chunk == data[i * 10_000..(i + 1) * 10_000],
+            "chunk {i} differs"
+        );
+    }
+
+    // Seeking drops the reads in flight and continues from the new position
+    let pos = file
+        .seek(SeekFrom::Current(-5_000))
+        .await
+        .expect("failed to seek");
+    assert_eq!(pos, 995_000);
+    file.read_exact(&mut chunk).await.expect("read failed");
+    assert!(chunk == data[995_000..1_005_000], "data after seek differs");
+
+    file.seek(SeekFrom::Start(3_000_000))
+        .await
+        .expect("failed to seek");
+    let mut rest = Vec::new();
+    file.read_to_end(&mut rest).await.expect("read failed");
+    assert!(rest == data[3_000_000..], "data up to the end differs");
+
+    // Reading at the end of file keeps returning nothing
+    assert_eq!(file.read(&mut chunk).await.expect("read failed"), 0);
+
+    drop(file);
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn read_ahead_eof() {
+    const DATA_LEN: usize = 100_000;
+
+    let client = client().await;
+
+    let name = rand_name();
+    let data = (0..DATA_LEN).map(|i| (i % 229) as u8).collect::<Vec<_>>();
+    client.write(&name, &data).await.expect("write() failed");
+
+    // Reading to the end stops reading ahead once it runs past the end of file
+    let mut file = client.open(&name).await.expect("open() failed");
+    file.set_max_buf_size(4096);
+    file.set_read_ahead(4);
+    let mut rdata = Vec::new();
+    file.read_to_end(&mut rdata).await.expect("read failed");
+    assert!(rdata == data, "data read to the end differs");
+    assert_eq!(file.read(&mut [0u8; 16]).await.expect("read failed"), 0);
+    drop(file);
+
+    // Same through the buffer
+    let mut file = client.open(&name).await.expect("open() failed");
+    file.set_max_buf_size(4096);
+    file.set_read_ahead(4);
+    let mut rdata = Vec::new();
+    loop {
+        let buf = file.fill_buf().await.expect("fill_buf() failed");
+        if buf.is_empty() {
+            break;
+        }
+        rdata.extend_from_slice(buf);
+        let n = buf.len();
+        file.consume(n);
+    }
+    assert!(rdata == data, "data read through the buffer differs");
+    assert!(file.fill_buf().await.expect("fill_buf() failed").is_empty());
+    drop(file);
+
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn buf_read() {
+    let client = client().await;
+
+    let name = rand_name();
+    let text = (0..10_000)
+        .map(|i| format!("line {i}\n"))
+        .collect::<String>();
+    client.write(&name, &text).await.expect("write() failed");
+
+    let mut file = client.open(&name).await.expect("open() failed");
+    // Lines cross the buffer boundaries
+    file.set_max_buf_size(1000);
+
+    let mut first = Vec::new();
+    file.read_until(b'\n', &mut first)
+        .await
+        .expect("read_until() failed");
+    assert_eq!(first, b"line 0\n");
+
+    let mut lines = file.lines();
+    let mut count = 1;
+    while let Some(line) = lines.next_line().await.expect("next_line() failed") {
+        assert_eq!(line, format!("line {count}"));
+        count += 1;
+    }
+    assert_eq!(count, 10_000);
+
+    drop(lines);
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn close() {
+    let client = client().await;
+
+    let name = rand_name();
+    let mut file = client.create(&name).await.expect("failed to create file");
+    file.set_max_buf_size(4096);
+    file.set_write_behind(4);
+
+    // Data still in flight is written out before the handle is closed
+    let wdata = (0..64 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    file.write_all(&wdata).await.expect("failed to write data");
+    file.close().await.expect("failed to close file");
+    assert_eq!(client.read(&name).await.expect("read() failed"), wdata);
+
+    // Closing a file that was only read doesn't need a commit, and waits for the reads ahead
+    // still in flight
+    let mut file = client.open(&name).await.expect("failed to open file");
+    file.set_max_buf_size(4096);
+    file.set_read_ahead(4);
+    let mut rdata = vec![0u8; 16 * 1024];
+    file.read_exact(&mut rdata)
+        .await
+        .expect("failed to read data");
+    assert_eq!(rdata, wdata[..rdata.len()]);
+    file.close().await.expect("failed to close file");
+
+    // Dropping a file closes it in the background
+    write_with(
+        &client,
+        nfs::OpenOptions::new().write(true).truncate(true),
+        &name,
+        b"dropped",
+    )
+    .await;
+    assert_eq!(client.read(&name).await.expect("read() failed"), b"dropped");
+
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn async_backend_streaming() {
+    const DATA_LEN: usize = 256 * 1024;
+
+    let client = nfs::Client::builder()
+        .backend(nfs::Backend::Async)
+        .mount(server())
+        .await
+        .expect("failed to mount NFS server");
+
+    let name = rand_name();
+    let wdata = (0..DATA_LEN).map(|i| (i % 241) as u8).collect::<Vec<_>>();
+
+    // Streaming writes, seeks and reads through the cursor
+    let mut file = nfs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&client, &name)
+        .await
+        .expect("failed to create file");
+    file.set_max_buf_size(4096);
+    file.write_all(&wdata).await.expect("failed to write data");
+    file.flush().await.expect("failed to flush data");
+
+    let pos = file
+        .seek(SeekFrom::Start(1000))
+        .await
+        .expect("failed to seek");
+    assert_eq!(pos, 1000);
+    let mut chunk = vec![0u8; 10_000];
+    file.read_exact(&mut chunk).await.expect("read failed");
+    assert!(chunk == wdata[1000..11_000], "data after seek differs");
+
+    file.seek(SeekFrom::Current(-5000))
+        .await
+        .expect("failed to seek");
+    file.write_all(b"overwritten")
+        .await
+        .expect("failed to write data");
+    file.flush().await.expect("failed to flush data");
+    file.close().await.expect("failed to close file");
+
+    let mut expected = wdata;
+    expected[6000..6011].copy_from_slice(b"overwritten");
+
+    // Positional writes behind and reads ahead
+    let mut file = nfs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(&client, &name)
+        .await
+        .expect("failed to open file");
+    file.set_max_buf_size(4096);
+    file.set_write_behind(8);
+    file.write_all(&expected)
+        .await
+        .expect("failed to write data");
+    file.close().await.expect("failed to close file");
+
+    let mut file = client.open(&name).await.expect("failed to open file");
+    file.set_max_buf_size(4096);
+    file.set_read_ahead(4);
+    let mut rdata = Vec::new();
+    file.read_to_end(&mut rdata)
+        .await
+        .expect("failed to read data");
+    assert!(rdata == expected, "data read ahead differs");
+    drop(file);
+
+    // Dropped files are closed in the background
+    write_with(
+        &client,
+        nfs::OpenOptions::new().write(true).truncate(true),
+        &name,
+        b"dropped",
+    )
+    .await;
+    assert_eq!(client.read(&name).await.expect("read() failed"), b"dropped");
+
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn client_dropped_first() {
+    let client = client().await;
+
+    let name = rand_name();
+    let mut file = client.create(&name).await.expect("failed to create file");
+    let mut dir = client.read_dir(".").await.expect("read_dir() failed");
+
+    // The files keep the connection alive
+    drop(client);
+
+    file.write_all(b"outlives the client")
+        .await
+        .expect("failed to write data");
+    file.flush().await.expect("failed to flush data");
+    assert_eq!(file.stat().await.expect("stat() failed").len(), 19);
+    assert!(futures_util::StreamExt::next(&mut dir).await.is_some());
+    drop(dir);
+    file.close().await.expect("failed to close file");
+
+    let client = support::client().await;
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("failed to umount");
+}
+
+#[tokio::test]
+async fn umount_with_open_files() {
+    let client = client().await;
+
+    let name = rand_name();
+    let file = client.create(&name).await.expect("failed to create file");
+
+    let Err(err) = client.umount().await else {
+        panic!("umount() succeeded with a file open");
+    };
+    assert_eq!(err.raw_os_error(), Some(nix::errno::Errno::EBUSY as i32));
+
+    // The export stays mounted
+    drop(file);
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("umount() failed");
+}
+
+#[tokio::test]
+async fn umount_with_writes_behind() {
+    let client = client().await;
+
+    let name = rand_name();
+    let mut file = client.create(&name).await.expect("failed to create file");
+    file.set_max_buf_size(4096);
+    file.set_write_behind(16);
+
+    // Dropped without flushing, so the writes are still in flight when unmounting
+    let data = (0..256 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    file.write_all(&data).await.expect("failed to write data");
+    drop(file);
+    client.umount().await.expect("umount() failed");
+
+    let client = support::client().await;
+    assert_eq!(client.read(&name).await.expect("read() failed"), data);
+    client.unlink(&name).await.expect("unlink() failed");
+    client.umount().await.expect("umount() failed");
+}
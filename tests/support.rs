@@ -1,15 +1,29 @@
 use nfs;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use std::env;
+use tokio::io::AsyncWriteExt;
 
-pub async fn client() -> nfs::Client {
-    let srv = env::var("TEST_NFS_SERVER").expect("TEST_NFS_SERVER not set");
+pub fn server() -> String {
+    env::var("TEST_NFS_SERVER").expect("TEST_NFS_SERVER not set")
+}
 
-    nfs::Client::mount(srv)
+pub async fn client() -> nfs::Client {
+    nfs::Client::mount(server())
         .await
         .expect("failed to mount NFS server")
 }
 
+// Opens `name` with `options`, writes `data` and drops the file once it's flushed.
+#[allow(dead_code)]
+pub async fn write_with(client: &nfs::Client, options: &nfs::OpenOptions, name: &str, data: &[u8]) {
+    let mut file = options
+        .open(client, name)
+        .await
+        .expect("failed to open file");
+    file.write_all(data).await.expect("failed to write data");
+    file.flush().await.expect("failed to flush data");
+}
+
 pub fn rand_name() -> String {
     thread_rng()
         .sample_iter(&Alphanumeric)